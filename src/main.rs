@@ -1,7 +1,7 @@
 use std::f32;
 use std::thread;
 use std::time::{Duration, Instant};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::{mpsc, Mutex, Arc};
 use std::sync::mpsc::channel;
 use std::ffi::CString;
@@ -11,7 +11,21 @@ use esp_idf_hal::peripherals::Peripherals;
 use esp_idf_hal::gpio::*;
 use esp_idf_hal::delay::{Ets, Delay};
 use esp_idf_hal::task::thread::ThreadSpawnConfiguration;
+use esp_idf_hal::i2c::{I2cDriver, I2cConfig};
+use esp_idf_hal::uart::{UartDriver, config::Config as UartConfig};
+use serde::{Serialize, Deserialize};
+use esp_idf_hal::units::FromValueType;
+use esp_idf_hal::adc::oneshot::{AdcDriver, AdcChannelDriver, config::AdcChannelConfig};
+use esp_idf_hal::adc::{ADC1, ADC2};
+use esp_idf_hal::spi::{SpiDeviceDriver, SpiDriver, SpiDriverConfig, config::Config as SpiConfig};
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
 use dht11::Dht11;
+use ssd1306::{Ssd1306, I2CDisplayInterface, size::DisplaySize128x64, rotation::DisplayRotation};
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::mono_font::{ascii::FONT_6X10, MonoTextStyle};
+use embedded_graphics::text::Text;
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
 
 
 enum ButtonEvent {
@@ -20,67 +34,151 @@ enum ButtonEvent {
     DoublePress
 }
 
+/// Rotation steps reported by a quadrature encoder's A/B channels; positive = clockwise. Kept
+/// on its own channel since it's unrelated to ButtonEvent's press/release semantics.
+#[derive(Clone, Copy)]
+enum InputEvent {
+    Rotate(i8),
+}
+
+#[derive(Clone, Copy)]
 enum RTCEvent {
-    Tick(u8, u8, u8) // (hour, minute, second)
+    Tick(u8, u8, u8),     // (hour, minute, second)
+    Date(u8, u8, u8, u8), // (day of month, month, day of week, year)
 }
 
+#[derive(Clone, Copy)]
 enum SensorData {
     Temperature(f32),
     Moisture(f32),
     Light(bool),
-    Pressure(f32)
+    Pressure(f32),
+    ProbeTemp(u8, f32), // (probe index on the OneWire bus, degrees C)
+    Battery(f32) // supply voltage in volts
+}
+
+/// Fans a single value out to every listening channel (e.g. both the display and radio tasks)
+/// so a stalled consumer on one channel never blocks producers feeding the others
+#[derive(Clone)]
+struct Broadcaster<T: Clone> {
+    senders: Vec<mpsc::Sender<T>>,
+}
+
+impl<T: Clone> Broadcaster<T> {
+    fn new(senders: Vec<mpsc::Sender<T>>) -> Self {
+        Broadcaster { senders }
+    }
+
+    fn send(&self, value: T) {
+        for sender in &self.senders {
+            let _ = sender.send(value.clone());
+        }
+    }
 }
 
+/// Below this supply voltage the red LED blinks regardless of access mode
+const LOW_BATTERY_THRESHOLD_V: f32 = 3.3;
+/// Resistor-divider ratio between the battery and the ADC pin (Vbat = Vadc * ratio)
+const BATTERY_DIVIDER_RATIO: f32 = 2.0;
+
 #[derive(PartialEq)]
 enum SetupMode {
     Idle,
     Hours,
     Minutes,
     Seconds,
+    Date,
+    Month,
+    Year,
+    AlarmHour,
+    AlarmMinute,
+    Target, // thermostat setpoint, in degrees C
+    CalibrateDry, // capture the soil-moisture sensor's "in air" reference reading
+    CalibrateWet, // capture the soil-moisture sensor's "in water" reference reading
 }
 
-/// Measure for how long the button is pressed in ms
-fn measure_press_duration(btn: &PinDriver<'static, AnyIOPin, Input>) -> u32 {
-    let start = Instant::now();
-    while btn.is_low() {}
-    start.elapsed().as_millis() as u32
+/// Sent from `rtc_task` to `display_task` when the current time matches the programmed alarm
+enum AlarmEvent {
+    Ring,
 }
 
-/// Check for a second short press after a first short press for a certain amount of time
-fn detect_double_press(btn: &PinDriver<'static, AnyIOPin, Input>, wait_ms: u32) -> bool {
-    let start = Instant::now();
-    while start.elapsed() < Duration::from_millis(wait_ms as u64) {
-        if btn.is_low() {
-            while btn.is_low() {}
-            return true;
-        }
-    }
-    false
+/// Sent from `thermostat_task` to `display_task` whenever the heater output flips, so the relay
+/// state can be shown alongside the setpoint and measured temperature
+#[derive(Clone, Copy)]
+enum ThermostatEvent {
+    HeaterOn,
+    HeaterOff,
 }
 
-/// Determine the type of button press
-fn determine_press_type(btn: &PinDriver<'static, AnyIOPin, Input>) -> ButtonEvent {
-    let duration = measure_press_duration(btn);
-    if duration >= 2000 {
-        // log::info!("Long press detected (pressed for {duration}ms)");
-        ButtonEvent::LongPress
-    }
-    else if duration > 0 && duration < 2000 {
-        if detect_double_press(btn, 300) {
-            // log::info!("Double press detected");
-            ButtonEvent::DoublePress
-        }
-        else {
-            // log::info!("Short press detected");
-            ButtonEvent::ShortPress
-        }
-    }
-    else {
-        // log::info!("Short press detected");
-        ButtonEvent::ShortPress
-    }
+/// Commands sent from `display_task` to `moisture_task` to capture a calibration reference point
+enum CalibrationCommand {
+    CaptureDry,
+    CaptureWet,
+}
+
+/// Commands sent from `serial_task` to `display_task` to drive the restricted/full-access mode
+/// from a host terminal instead of only the DoublePress button gesture
+enum ModeCommand {
+    SetFull,
+    SetRestricted,
+}
+
+/// Wall-clock time, shared by the `HostMessage`/`DeviceMessage` wire protocol
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct Time {
+    hour: u8,
+    minute: u8,
+    second: u8,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+enum Mode {
+    Full,
+    Restricted,
 }
 
+/// Requests a desktop tool can send to `protocol_task` over the COBS-framed binary channel
+#[derive(Serialize, Deserialize, Clone, Copy)]
+enum HostMessage {
+    SetTime(Time),
+    RequestStatus,
+    SetMode(Mode),
+    SetAlarm(Time),
+}
+
+/// Replies `protocol_task` sends back, in place of the ad-hoc `log::info!` dumps elsewhere
+#[derive(Serialize, Deserialize, Clone, Copy)]
+enum DeviceMessage {
+    Status { time: Time, temperature: f32, humidity: f32, light: bool, pressure: f32 },
+    Ack,
+    Error,
+}
+
+/// Press duration at or above which a release is reported as a long press
+const LONG_PRESS_MS: u128 = 2000;
+/// Window after a qualifying short press to watch for a second one (double press)
+const DOUBLE_PRESS_WINDOW_MS: u64 = 300;
+/// Minimum gap between two button edges; anything closer is assumed to be contact bounce and
+/// is dropped before it reaches the press state machine
+const DEBOUNCE_MS: u128 = 30;
+
+/// DS1302 spare-RAM addresses (0-30) used to persist the alarm across reboots
+const ALARM_HOUR_RAM_ADDR: u8 = 0;
+const ALARM_MINUTE_RAM_ADDR: u8 = 1;
+const ALARM_ENABLED_RAM_ADDR: u8 = 2;
+
+/// Degrees C per `SetupMode::Target` increment
+const TARGET_STEP_C: f32 = 0.5;
+/// Setpoint range, to keep the heater output from being pinned permanently on/off by a fat-finger edit
+const TARGET_MIN_C: f32 = 5.0;
+const TARGET_MAX_C: f32 = 35.0;
+/// Dead-band around the setpoint the heater output must cross before flipping, so it doesn't
+/// chatter on every small fluctuation around the target
+const THERMOSTAT_HYSTERESIS_C: f32 = 0.5;
+/// If no fresh `SensorData::Temperature` arrives within this window, fall back to heater-off
+/// rather than keep driving the output off a reading that may no longer be true
+const THERMOSTAT_SENSOR_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Calculate atmospheric pressure from temperature and humidity
 fn get_atm_pressure(t_c: f32, hour: f32) -> f32 {
     let t0 = t_c + 273.15;
@@ -107,6 +205,22 @@ fn dec_to_bcd(dec: u8) -> u8 {
     ((dec/10) << 4) | (dec%10)
 }
 
+/// `year` is the DS1302's 2-digit year (offset from 2000)
+fn is_leap_year(year: u8) -> bool {
+    let y = 2000 + year as u32;
+    (y % 4 == 0 && y % 100 != 0) || (y % 400 == 0)
+}
+
+/// Number of days in `month` (1-12) of `year`, for clamping the date field after an edit
+fn days_in_month(month: u8, year: u8) -> u8 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 => if is_leap_year(year) { 29 } else { 28 },
+        _ => 31,
+    }
+}
+
 struct RTCInterface {
     sclk: PinDriver<'static, Gpio1, Output>,
     io: Option<PinDriver<'static, Gpio2, Output>>,
@@ -115,6 +229,10 @@ struct RTCInterface {
     hours: u8,
     minutes: u8,
     seconds: u8,
+    date: u8,  // day of month, 1-31
+    month: u8, // 1-12
+    day: u8,   // day of week, 1-7 (shown by draw_date as 1 = Mon .. 7 = Sun)
+    year: u8,  // 2-digit, offset from 2000
 }
 
 impl RTCInterface {
@@ -124,6 +242,10 @@ impl RTCInterface {
         let hours = dec_to_bcd(14);
         let minutes = dec_to_bcd(50);
         let seconds = dec_to_bcd(00);
+        let date = dec_to_bcd(1);
+        let month = dec_to_bcd(1);
+        let day = dec_to_bcd(1);
+        let year = dec_to_bcd(0);
 
         // /!\ You can also set the clock directly to an initial time by uncommenting the following comment block (comment it back or it will reset on every run) /!\ //
         /*
@@ -230,93 +352,113 @@ impl RTCInterface {
         */
 
         // SET FIELDS //
-        RTCInterface { sclk, io, ce, delay, hours, minutes, seconds }
+        RTCInterface { sclk, io, ce, delay, hours, minutes, seconds, date, month, day, year }
     }
 
     /// Iterate seconds register
-    fn iterate_second(&mut self) {
+    fn iterate_second(&mut self) { self.iterate_second_by(1); }
+
+    /// Move the seconds register by `steps` (positive or negative), wrapping within 0-59.
+    /// Factored out of `iterate_second` so the rotary encoder can move several steps at once.
+    fn iterate_second_by(&mut self, steps: i32) {
         self.read();
-        let io_pin = self.io.as_mut().unwrap();
-        self.ce.set_low().unwrap();
-        // start transaction
-        self.sclk.set_low().unwrap();
-        self.ce.set_high().unwrap();
-        // write byte 0x80 on control register using IO pin
-        io_pin.set_low().unwrap();
-        for i in 0..8 {
-            self.sclk.set_low().unwrap();
-            if (0x80 >> i) & 0x01 == 1 { io_pin.set_high().unwrap(); } else { io_pin.set_low().unwrap(); }
-            self.delay.delay_us(1);
-            self.sclk.set_high().unwrap();
-            self.delay.delay_us(1);
-        }
-        if self.seconds == 59 { self.seconds = dec_to_bcd(0); } else { self.seconds = dec_to_bcd(self.seconds+1); }
-        // send data seconds and clear bit 7
-        let sec_val = self.seconds & 0x7F;
-        for i in 0..8 {
-            self.sclk.set_low().unwrap();
-            if (sec_val >> i) & 0x01 == 1 { io_pin.set_high().unwrap(); } else { io_pin.set_low().unwrap(); }
-            self.delay.delay_us(1);
-            self.sclk.set_high().unwrap();
-            self.delay.delay_us(1);
-        }
-        // end transaction
-        self.ce.set_low().unwrap();
+        let new_val = (self.seconds as i32 + steps).rem_euclid(60) as u8;
+        self.seconds = dec_to_bcd(new_val);
+        self.write_register(0x80, self.seconds & 0x7F);
     }
 
     /// Iterate minutes register
-    fn iterate_minute(&mut self) {
+    fn iterate_minute(&mut self) { self.iterate_minute_by(1); }
+
+    /// Move the minutes register by `steps`, wrapping within 0-59
+    fn iterate_minute_by(&mut self, steps: i32) {
         self.read();
-        let io_pin = self.io.as_mut().unwrap();
-        self.ce.set_low().unwrap();
-        // start transaction
-        self.sclk.set_low().unwrap();
-        self.ce.set_high().unwrap();
-        // write byte 0x82 on control register using IO pin
-        io_pin.set_low().unwrap();
-        for i in 0..8 {
-            self.sclk.set_low().unwrap();
-            if (0x82 >> i) & 0x01 == 1 { io_pin.set_high().unwrap(); } else { io_pin.set_low().unwrap(); }
-            self.delay.delay_us(1);
-            self.sclk.set_high().unwrap();
-            self.delay.delay_us(1);
-        }
-        if self.minutes == 59 { self.minutes = dec_to_bcd(0); } else { self.minutes = dec_to_bcd(self.minutes+1); }
-        // send data minutes
-        for i in 0..8 {
-            self.sclk.set_low().unwrap();
-            if (self.minutes >> i) & 0x01 == 1 { io_pin.set_high().unwrap(); } else { io_pin.set_low().unwrap(); }
-            self.delay.delay_us(1);
-            self.sclk.set_high().unwrap();
-            self.delay.delay_us(1);
-        }
-        // end transaction
-        self.ce.set_low().unwrap();
+        let new_val = (self.minutes as i32 + steps).rem_euclid(60) as u8;
+        self.minutes = dec_to_bcd(new_val);
+        self.write_register(0x82, self.minutes);
     }
 
     /// Iterate hours register
-    fn iterate_hour(&mut self) {
+    fn iterate_hour(&mut self) { self.iterate_hour_by(1); }
+
+    /// Move the hours register by `steps`, wrapping within 0-23 (24h mode)
+    fn iterate_hour_by(&mut self, steps: i32) {
+        self.read();
+        let new_val = (self.hours as i32 + steps).rem_euclid(24) as u8;
+        self.hours = dec_to_bcd(new_val);
+        self.write_register(0x84, self.hours & 0x3F);
+    }
+
+    /// Iterate date (day of month) register, wrapping at the current month's length
+    fn iterate_date(&mut self) { self.iterate_date_by(1); }
+
+    /// Move the date register by `steps`, wrapping within 1..=days_in_month(month, year)
+    fn iterate_date_by(&mut self, steps: i32) {
+        self.read();
+        let max_day = days_in_month(self.month, self.year);
+        let new_val = 1 + (self.date as i32 - 1 + steps).rem_euclid(max_day as i32) as u8;
+        self.date = dec_to_bcd(new_val);
+        self.write_register(0x86, self.date);
+    }
+
+    /// Iterate month register, wrapping from 12 back to 1. Clamps the date field down if it
+    /// doesn't exist in the new month (e.g. Jan 31 -> Feb 28/29)
+    fn iterate_month(&mut self) { self.iterate_month_by(1); }
+
+    /// Move the month register by `steps`, wrapping within 1-12
+    fn iterate_month_by(&mut self, steps: i32) {
+        self.read();
+        let new_val = 1 + (self.month as i32 - 1 + steps).rem_euclid(12) as u8;
+        self.month = dec_to_bcd(new_val);
+        self.write_register(0x88, self.month);
+
+        let max_day = days_in_month(new_val, self.year);
+        if self.date > max_day {
+            self.date = dec_to_bcd(max_day);
+            self.write_register(0x86, self.date);
+        }
+    }
+
+    /// Iterate year register (2-digit, offset from 2000), wrapping at 99. Re-clamps the date
+    /// field since toggling leap years can shrink February
+    fn iterate_year(&mut self) { self.iterate_year_by(1); }
+
+    /// Move the year register by `steps`, wrapping within 0-99
+    fn iterate_year_by(&mut self, steps: i32) {
         self.read();
+        let new_val = (self.year as i32 + steps).rem_euclid(100) as u8;
+        self.year = dec_to_bcd(new_val);
+        self.write_register(0x8C, self.year);
+
+        let max_day = days_in_month(self.month, new_val);
+        if self.date > max_day {
+            self.date = dec_to_bcd(max_day);
+            self.write_register(0x86, self.date);
+        }
+    }
+
+    /// Write `value_bcd` to register `addr` in one transaction bracketed by raising and lowering
+    /// CE; the control register's write-protect bit is not changed here. Factored out once the per-field iterate_* writers started duplicating the
+    /// same bit-bang transaction shape.
+    fn write_register(&mut self, addr: u8, value_bcd: u8) {
         let io_pin = self.io.as_mut().unwrap();
         self.ce.set_low().unwrap();
         // start transaction
         self.sclk.set_low().unwrap();
         self.ce.set_high().unwrap();
-        // write byte 0x84 on control register using IO pin
+        // write register address byte
         io_pin.set_low().unwrap();
         for i in 0..8 {
             self.sclk.set_low().unwrap();
-            if (0x84 >> i) & 0x01 == 1 { io_pin.set_high().unwrap(); } else { io_pin.set_low().unwrap(); }
+            if (addr >> i) & 0x01 == 1 { io_pin.set_high().unwrap(); } else { io_pin.set_low().unwrap(); }
             self.delay.delay_us(1);
             self.sclk.set_high().unwrap();
             self.delay.delay_us(1);
         }
-        if self.hours == 23 { self.hours = dec_to_bcd(0); } else { self.hours = dec_to_bcd(self.hours+1); }
-        // send data hours and set bit 7 to 0 for 24h mode
-        let hrs = self.hours & 0x3F;
+        // write data byte
         for i in 0..8 {
             self.sclk.set_low().unwrap();
-            if (hrs >> i) & 0x01 == 1 { io_pin.set_high().unwrap(); } else { io_pin.set_low().unwrap(); }
+            if (value_bcd >> i) & 0x01 == 1 { io_pin.set_high().unwrap(); } else { io_pin.set_low().unwrap(); }
             self.delay.delay_us(1);
             self.sclk.set_high().unwrap();
             self.delay.delay_us(1);
@@ -365,10 +507,33 @@ impl RTCInterface {
             hr_val |= bit << i;
             self.sclk.set_high().unwrap();
         }
-        // skip unused registers (date, month, year, etc)
-        for _ in 0..(8 * 4) {
+        // read date, month, day (of week) and year
+        let mut date_val = 0;
+        for i in 0..8 {
+            self.sclk.set_low().unwrap();
+            let bit = if input_pin.is_high() { 1 } else { 0 };
+            date_val |= bit << i;
+            self.sclk.set_high().unwrap();
+        }
+        let mut month_val = 0;
+        for i in 0..8 {
+            self.sclk.set_low().unwrap();
+            let bit = if input_pin.is_high() { 1 } else { 0 };
+            month_val |= bit << i;
+            self.sclk.set_high().unwrap();
+        }
+        let mut day_val = 0;
+        for i in 0..8 {
             self.sclk.set_low().unwrap();
-            let _ = if input_pin.is_high() { 1 } else { 0 };
+            let bit = if input_pin.is_high() { 1 } else { 0 };
+            day_val |= bit << i;
+            self.sclk.set_high().unwrap();
+        }
+        let mut year_val = 0;
+        for i in 0..8 {
+            self.sclk.set_low().unwrap();
+            let bit = if input_pin.is_high() { 1 } else { 0 };
+            year_val |= bit << i;
             self.sclk.set_high().unwrap();
         }
         // end transaction
@@ -380,55 +545,885 @@ impl RTCInterface {
         sec_val = bcd_to_dec(sec_val & 0x7F);
         min_val = bcd_to_dec(min_val & 0x7F);
         hr_val = bcd_to_dec(hr_val & 0x3F);
+        date_val = bcd_to_dec(date_val & 0x3F);
+        month_val = bcd_to_dec(month_val & 0x1F);
+        day_val = bcd_to_dec(day_val & 0x07);
+        year_val = bcd_to_dec(year_val);
         self.seconds = sec_val;
         self.minutes = min_val;
         self.hours = hr_val;
+        self.date = date_val;
+        self.month = month_val;
+        self.day = day_val;
+        self.year = year_val;
+    }
+
+    /// Write a single byte to the DS1302's battery-backed RAM (31 bytes, address 0-30), using
+    /// its "1 1 AAAAA RD/W#" command byte shape (vs. `write_register`'s clock/calendar one)
+    fn write_ram(&mut self, addr: u8, value: u8) {
+        let command = 0xC0 | (addr << 1);
+        let io_pin = self.io.as_mut().unwrap();
+        self.ce.set_low().unwrap();
+        // start transaction
+        self.sclk.set_low().unwrap();
+        self.ce.set_high().unwrap();
+        // write command byte
+        io_pin.set_low().unwrap();
+        for i in 0..8 {
+            self.sclk.set_low().unwrap();
+            if (command >> i) & 0x01 == 1 { io_pin.set_high().unwrap(); } else { io_pin.set_low().unwrap(); }
+            self.delay.delay_us(1);
+            self.sclk.set_high().unwrap();
+            self.delay.delay_us(1);
+        }
+        // write data byte
+        for i in 0..8 {
+            self.sclk.set_low().unwrap();
+            if (value >> i) & 0x01 == 1 { io_pin.set_high().unwrap(); } else { io_pin.set_low().unwrap(); }
+            self.delay.delay_us(1);
+            self.sclk.set_high().unwrap();
+            self.delay.delay_us(1);
+        }
+        // end transaction
+        self.ce.set_low().unwrap();
+    }
+
+    /// Read a single byte back from the DS1302's battery-backed RAM
+    fn read_ram(&mut self, addr: u8) -> u8 {
+        let command = 0xC0 | (addr << 1) | 0x01;
+        self.io.as_mut().unwrap().set_low().unwrap();
+        // begin transaction
+        self.sclk.set_low().unwrap();
+        self.ce.set_high().unwrap();
+        self.delay.delay_us(1);
+        // send the command byte
+        for i in 0..8 {
+            self.sclk.set_low().unwrap();
+            if (command >> i) & 0x01 == 1 { self.io.as_mut().unwrap().set_high().unwrap(); }
+            else { self.io.as_mut().unwrap().set_low().unwrap(); }
+            self.delay.delay_us(1);
+            self.sclk.set_high().unwrap();
+            self.delay.delay_us(1);
+        }
+        // set io_pin to input mode
+        let input_pin = self.io.take().unwrap().into_input().unwrap();
+        let mut value = 0;
+        for i in 0..8 {
+            self.sclk.set_low().unwrap();
+            let bit = if input_pin.is_high() { 1 } else { 0 };
+            value |= bit << i;
+            self.sclk.set_high().unwrap();
+        }
+        // end transaction
+        self.ce.set_low().unwrap();
+        // set io_pin back to output
+        self.io = Some(input_pin.into_output().unwrap());
+        value
+    }
+
+    /// Persist the alarm time to the spare RAM bytes, so it survives a power cycle like the
+    /// clock itself. `ALARM_ENABLED_RAM_ADDR` doubles as a "has this ever been programmed" flag.
+    fn set_alarm(&mut self, hour: u8, minute: u8) {
+        self.write_ram(ALARM_HOUR_RAM_ADDR, dec_to_bcd(hour));
+        self.write_ram(ALARM_MINUTE_RAM_ADDR, dec_to_bcd(minute));
+        self.write_ram(ALARM_ENABLED_RAM_ADDR, 1);
+    }
+
+    /// Read back the persisted alarm time, if one has ever been programmed
+    fn get_alarm(&mut self) -> Option<(u8, u8)> {
+        if self.read_ram(ALARM_ENABLED_RAM_ADDR) == 1 {
+            let hour = bcd_to_dec(self.read_ram(ALARM_HOUR_RAM_ADDR));
+            let minute = bcd_to_dec(self.read_ram(ALARM_MINUTE_RAM_ADDR));
+            Some((hour, minute))
+        } else {
+            None
+        }
+    }
+}
+
+
+/// Reconstruct ShortPress/LongPress/DoublePress from timestamped edges posted by a GPIO interrupt
+/// instead of busy-polling `btn.is_low()`, so the CPU is free between presses and a 2s long press
+/// no longer blocks anything else. The pin is pulled up, so edges alternate: falling (pressed)
+/// then rising (released).
+fn button_task(tx_button: mpsc::Sender<ButtonEvent>, mut btn_pin: PinDriver<'static, AnyIOPin, Input>) {
+    btn_pin.set_interrupt_type(InterruptType::AnyEdge).unwrap();
+    let edges: Arc<Mutex<VecDeque<Instant>>> = Arc::new(Mutex::new(VecDeque::new()));
+    let edges_isr = Arc::clone(&edges);
+    unsafe {
+        btn_pin.subscribe(move || {
+            edges_isr.lock().unwrap().push_back(Instant::now());
+        }).unwrap();
+    }
+    btn_pin.enable_interrupt().unwrap();
+
+    let mut falling_next = true;
+    let mut press_start: Option<Instant> = None;
+    let mut pending_short: Option<Instant> = None; // set while waiting out the double-press window
+    let mut last_edge: Option<Instant> = None; // debounce: last edge accepted into the state machine
+
+    loop {
+        let pending_edges: Vec<Instant> = edges.lock().unwrap().drain(..).collect();
+        for at in pending_edges {
+            // re-arm unconditionally: interrupts are one-shot, and a bounced edge still needs a
+            // fresh interrupt armed to catch the next real one
+            btn_pin.enable_interrupt().unwrap();
+            if last_edge.is_some_and(|last| at.duration_since(last).as_millis() < DEBOUNCE_MS) {
+                continue; // contact bounce, not a real edge
+            }
+            last_edge = Some(at);
+            if falling_next {
+                if pending_short.take().is_some() {
+                    tx_button.send(ButtonEvent::DoublePress).unwrap();
+                } else {
+                    press_start = Some(at);
+                }
+            } else if let Some(start) = press_start.take() {
+                if at.duration_since(start).as_millis() >= LONG_PRESS_MS {
+                    tx_button.send(ButtonEvent::LongPress).unwrap();
+                } else {
+                    pending_short = Some(at);
+                }
+            }
+            falling_next = !falling_next;
+        }
+        if pending_short.is_some_and(|since| since.elapsed() >= Duration::from_millis(DOUBLE_PRESS_WINDOW_MS)) {
+            pending_short = None;
+            tx_button.send(ButtonEvent::ShortPress).unwrap();
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Gray-code transition table for a quadrature encoder, indexed by `(previous_state << 2) |
+/// current_state` where each state packs the A/B channel levels into 2 bits. +1/-1 for a valid
+/// single-step transition, 0 for no movement or an invalid (bounced) jump.
+const QUADRATURE_TRANSITIONS: [i8; 16] = [
+     0,  1, -1,  0,
+    -1,  0,  0,  1,
+     1,  0,  0, -1,
+     0, -1,  1,  0,
+];
+/// Quarter-steps per detent on a typical clicked rotary encoder
+const QUADRATURE_STEPS_PER_DETENT: i8 = 4;
+
+/// Decode a quadrature rotary encoder's A/B channels into `InputEvent::Rotate` steps. Like
+/// `button_task`, the ISRs only record that *an* edge fired; the actual A/B levels are sampled
+/// back on this thread (which owns the pins) rather than inside interrupt context.
+fn encoder_task(tx_rotate: mpsc::Sender<InputEvent>, mut pin_a: PinDriver<'static, AnyIOPin, Input>, mut pin_b: PinDriver<'static, AnyIOPin, Input>) {
+    pin_a.set_interrupt_type(InterruptType::AnyEdge).unwrap();
+    pin_b.set_interrupt_type(InterruptType::AnyEdge).unwrap();
+    let edge_seen = Arc::new(Mutex::new(false));
+    let edge_seen_a = Arc::clone(&edge_seen);
+    let edge_seen_b = Arc::clone(&edge_seen);
+    unsafe {
+        pin_a.subscribe(move || { *edge_seen_a.lock().unwrap() = true; }).unwrap();
+        pin_b.subscribe(move || { *edge_seen_b.lock().unwrap() = true; }).unwrap();
+    }
+    pin_a.enable_interrupt().unwrap();
+    pin_b.enable_interrupt().unwrap();
+
+    let mut last_state = ((pin_a.is_high() as u8) << 1) | (pin_b.is_high() as u8);
+
+    // the quarter-step -> detent accumulation happens downstream in `display_task`, which is the
+    // one that knows when the selected field changes and needs to reset it; this task only
+    // decodes raw quarter-steps and debounces by dropping the table's 0 (no-movement/illegal) entries
+    loop {
+        let had_edge = {
+            let mut seen = edge_seen.lock().unwrap();
+            let had = *seen;
+            *seen = false;
+            had
+        };
+        if had_edge {
+            let state = ((pin_a.is_high() as u8) << 1) | (pin_b.is_high() as u8);
+            let step = QUADRATURE_TRANSITIONS[((last_state << 2) | state) as usize];
+            if step != 0 {
+                tx_rotate.send(InputEvent::Rotate(step)).unwrap();
+            }
+            last_state = state;
+            // interrupts are one-shot; re-arm after every edge
+            pin_a.enable_interrupt().unwrap();
+            pin_b.enable_interrupt().unwrap();
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// Read the RTC every second and send event to the display task
+fn rtc_task(tx_rtc: Broadcaster<RTCEvent>, tx_alarm: mpsc::Sender<AlarmEvent>, rtc_mutex: Arc<Mutex<RTCInterface>>) {
+    loop {
+        let mut rtc = rtc_mutex.lock().unwrap();
+        rtc.read();
+        let hour = rtc.hours;
+        let minute = rtc.minutes;
+        let second = rtc.seconds;
+        let date = rtc.date;
+        let month = rtc.month;
+        let day = rtc.day;
+        let year = rtc.year;
+        // only check at second 0, so a match rings once per minute rather than on every tick
+        if second == 0 {
+            if let Some((alarm_hour, alarm_minute)) = rtc.get_alarm() {
+                if alarm_hour == hour && alarm_minute == minute {
+                    tx_alarm.send(AlarmEvent::Ring).unwrap();
+                }
+            }
+        }
+        tx_rtc.send(RTCEvent::Tick(hour, minute, second));
+        tx_rtc.send(RTCEvent::Date(date, month, day, year));
+        drop(rtc); // drop the lock
+        thread::sleep(Duration::from_millis(1000));
+    }
+}
+
+/// Render one sensor reading the way a human would type or read it back over the CLI
+fn format_sensor_value(value: Option<&SensorData>) -> String {
+    match value {
+        Some(SensorData::Temperature(t)) => format!("{t:.1}C"),
+        Some(SensorData::Moisture(h)) => format!("{h:.1}%"),
+        Some(SensorData::Light(on)) => if *on { "Bright".to_string() } else { "Dark".to_string() },
+        Some(SensorData::Pressure(p)) => format!("{p:.1}hPa"),
+        Some(SensorData::ProbeTemp(idx, t)) => format!("probe[{idx}]={t:.1}C"),
+        Some(SensorData::Battery(v)) => format!("{v:.2}V"),
+        None => "N/A".to_string(),
+    }
+}
+
+/// Parse and execute one line of the serial command grammar, returning the text to reply with.
+/// Grammar: `set time HH:MM:SS`, `set alarm HH:MM`, `get temp|light|pressure|moisture|battery`, `mode full|restricted`, `dump`.
+fn handle_serial_command(line: &str, rtc_mutex: &Arc<Mutex<RTCInterface>>, latest: &HashMap<&'static str, SensorData>, tx_mode: &mpsc::Sender<ModeCommand>) -> String {
+    let tokens: Vec<&str> = line.trim().split_whitespace().collect();
+    match tokens.as_slice() {
+        ["set", "time", hms] => {
+            let parts: Vec<&str> = hms.split(':').collect();
+            let parsed = match parts.as_slice() {
+                [h, m, s] => (h.parse::<i32>(), m.parse::<i32>(), s.parse::<i32>()),
+                _ => return "ERR expected HH:MM:SS".to_string(),
+            };
+            if let (Ok(h), Ok(m), Ok(s)) = parsed {
+                // feed the same iterate_*_by path display_task uses, so the DS1302
+                // write logic isn't duplicated here
+                let mut rtc = rtc_mutex.lock().unwrap();
+                rtc.read();
+                let (dh, dm, ds) = (h - rtc.hours as i32, m - rtc.minutes as i32, s - rtc.seconds as i32);
+                rtc.iterate_hour_by(dh);
+                rtc.iterate_minute_by(dm);
+                rtc.iterate_second_by(ds);
+                "OK".to_string()
+            } else {
+                "ERR expected HH:MM:SS".to_string()
+            }
+        },
+        ["set", "alarm", hm] => {
+            let parts: Vec<&str> = hm.split(':').collect();
+            match parts.as_slice() {
+                [h, m] => match (h.parse::<u8>(), m.parse::<u8>()) {
+                    (Ok(h), Ok(m)) if h < 24 && m < 60 => {
+                        rtc_mutex.lock().unwrap().set_alarm(h, m);
+                        "OK".to_string()
+                    },
+                    _ => "ERR expected HH:MM".to_string(),
+                },
+                _ => "ERR expected HH:MM".to_string(),
+            }
+        },
+        ["get", "temp"] => format_sensor_value(latest.get("temperature")),
+        ["get", "light"] => format_sensor_value(latest.get("light")),
+        ["get", "pressure"] => format_sensor_value(latest.get("pressure")),
+        ["get", "moisture"] => format_sensor_value(latest.get("moisture")),
+        ["get", "battery"] => format_sensor_value(latest.get("battery")),
+        ["mode", "full"] => { tx_mode.send(ModeCommand::SetFull).unwrap(); "OK".to_string() },
+        ["mode", "restricted"] => { tx_mode.send(ModeCommand::SetRestricted).unwrap(); "OK".to_string() },
+        ["dump"] => {
+            let mut rtc = rtc_mutex.lock().unwrap();
+            rtc.read();
+            format!(
+                "time={:02}:{:02}:{:02} date={:02}/{:02}/20{:02} temp={} moisture={} light={} pressure={} battery={}",
+                rtc.hours, rtc.minutes, rtc.seconds, rtc.date, rtc.month, rtc.year,
+                format_sensor_value(latest.get("temperature")),
+                format_sensor_value(latest.get("moisture")),
+                format_sensor_value(latest.get("light")),
+                format_sensor_value(latest.get("pressure")),
+                format_sensor_value(latest.get("battery")),
+            )
+        },
+        [] => String::new(),
+        _ => "ERR unknown command".to_string(),
+    }
+}
+
+/// Read lines from a UART and act on a small command grammar for configuring the clock and
+/// querying sensors, so the device can be driven from a host terminal instead of only the
+/// physical button/encoder.
+fn serial_task(mut uart: UartDriver<'static>, rtc_mutex: Arc<Mutex<RTCInterface>>, rx_sensor: mpsc::Receiver<SensorData>, tx_mode: mpsc::Sender<ModeCommand>) {
+    let mut latest: HashMap<&'static str, SensorData> = HashMap::new();
+    let mut line = String::new();
+    let mut buf = [0u8; 32];
+
+    loop {
+        // refresh the cached sensor readings from the broadcast channel
+        while let Ok(sensor) = rx_sensor.try_recv() {
+            match sensor {
+                SensorData::Temperature(_) => { latest.insert("temperature", sensor); },
+                SensorData::Moisture(_) => { latest.insert("moisture", sensor); },
+                SensorData::Light(_) => { latest.insert("light", sensor); },
+                SensorData::Pressure(_) => { latest.insert("pressure", sensor); },
+                SensorData::ProbeTemp(_, _) => {}, // not exposed over the CLI grammar
+                SensorData::Battery(_) => { latest.insert("battery", sensor); },
+            }
+        }
+
+        if let Ok(n) = uart.read(&mut buf, 20) {
+            for &b in &buf[..n] {
+                match b {
+                    b'\n' | b'\r' => {
+                        if !line.is_empty() {
+                            let reply = handle_serial_command(&line, &rtc_mutex, &latest, &tx_mode);
+                            let _ = uart.write(reply.as_bytes());
+                            let _ = uart.write(b"\r\n");
+                            line.clear();
+                        }
+                    },
+                    _ => line.push(b as char),
+                }
+            }
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Max encoded frame size (COBS overhead + postcard payload) for one `HostMessage`/`DeviceMessage`
+const PROTOCOL_FRAME_CAP: usize = 64;
+
+/// Service one decoded `HostMessage`, locking `rtc_mutex` and reading from the cached sensor map
+/// the same way `handle_serial_command` does for the text CLI
+fn handle_host_message(msg: HostMessage, rtc_mutex: &Arc<Mutex<RTCInterface>>, latest: &HashMap<&'static str, SensorData>, tx_mode: &mpsc::Sender<ModeCommand>) -> DeviceMessage {
+    match msg {
+        HostMessage::SetTime(time) => {
+            let mut rtc = rtc_mutex.lock().unwrap();
+            rtc.read();
+            let dh = time.hour as i32 - rtc.hours as i32;
+            let dm = time.minute as i32 - rtc.minutes as i32;
+            let ds = time.second as i32 - rtc.seconds as i32;
+            rtc.iterate_hour_by(dh);
+            rtc.iterate_minute_by(dm);
+            rtc.iterate_second_by(ds);
+            DeviceMessage::Ack
+        },
+        HostMessage::SetMode(mode) => {
+            match mode {
+                Mode::Full => tx_mode.send(ModeCommand::SetFull).unwrap(),
+                Mode::Restricted => tx_mode.send(ModeCommand::SetRestricted).unwrap(),
+            }
+            DeviceMessage::Ack
+        },
+        HostMessage::SetAlarm(time) => {
+            rtc_mutex.lock().unwrap().set_alarm(time.hour, time.minute);
+            DeviceMessage::Ack
+        },
+        HostMessage::RequestStatus => {
+            let mut rtc = rtc_mutex.lock().unwrap();
+            rtc.read();
+            let time = Time { hour: rtc.hours, minute: rtc.minutes, second: rtc.seconds };
+            drop(rtc);
+            let temperature = match latest.get("temperature") { Some(SensorData::Temperature(t)) => *t, _ => 0.0 };
+            // no ambient humidity channel exists; report the soil-moisture percentage instead
+            let humidity = match latest.get("moisture") { Some(SensorData::Moisture(h)) => *h, _ => 0.0 };
+            let light = matches!(latest.get("light"), Some(SensorData::Light(true)));
+            let pressure = match latest.get("pressure") { Some(SensorData::Pressure(p)) => *p, _ => 0.0 };
+            DeviceMessage::Status { time, temperature, humidity, light, pressure }
+        },
+    }
+}
+
+/// Typed, COBS-framed binary alternative to `serial_task`'s text grammar: each `HostMessage` is
+/// `postcard::to_vec_cobs`-encoded, which appends the 0x00 sentinel that only ever appears as a
+/// frame boundary, so a partial or garbled read resyncs by discarding up to the next zero byte.
+fn protocol_task(mut uart: UartDriver<'static>, rtc_mutex: Arc<Mutex<RTCInterface>>, rx_sensor: mpsc::Receiver<SensorData>, tx_mode: mpsc::Sender<ModeCommand>) {
+    let mut latest: HashMap<&'static str, SensorData> = HashMap::new();
+    let mut frame: heapless::Vec<u8, PROTOCOL_FRAME_CAP> = heapless::Vec::new();
+    let mut byte = [0u8; 1];
+
+    loop {
+        while let Ok(sensor) = rx_sensor.try_recv() {
+            match sensor {
+                SensorData::Temperature(_) => { latest.insert("temperature", sensor); },
+                SensorData::Moisture(_) => { latest.insert("moisture", sensor); },
+                SensorData::Light(_) => { latest.insert("light", sensor); },
+                SensorData::Pressure(_) => { latest.insert("pressure", sensor); },
+                SensorData::ProbeTemp(_, _) => {},
+                SensorData::Battery(_) => { latest.insert("battery", sensor); },
+            }
+        }
+
+        if let Ok(n) = uart.read(&mut byte, 20) {
+            if n > 0 {
+                let b = byte[0];
+                if frame.push(b).is_err() {
+                    // frame overran the buffer; drop it and resync on the next zero byte
+                    frame.clear();
+                }
+                if b == 0x00 {
+                    let reply = match postcard::from_bytes_cobs::<HostMessage>(&mut frame) {
+                        Ok(msg) => handle_host_message(msg, &rtc_mutex, &latest, &tx_mode),
+                        Err(_) => DeviceMessage::Error,
+                    };
+                    if let Ok(encoded) = postcard::to_vec_cobs::<DeviceMessage, PROTOCOL_FRAME_CAP>(&reply) {
+                        let _ = uart.write(&encoded);
+                    }
+                    frame.clear();
+                }
+            }
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// Read the light level and temperature, calculate the pressure and send events to the display task
+/// (soil moisture is read separately by `moisture_task`, the DHT11 humidity is only used for pressure)
+fn sensor_task(tx_sensor: Broadcaster<SensorData>, mut dht11: Dht11<PinDriver<'static, AnyIOPin, InputOutput>>, light_pin: PinDriver<'static, Gpio38, Input>) {
+    loop {
+        if let Ok(measurement) = dht11.perform_measurement(&mut Ets) {
+            let temperature = measurement.temperature as f32 /10.0;
+            let humidity = measurement.humidity as f32 /10.0;
+            let pressure = get_atm_pressure(temperature, humidity);
+            tx_sensor.send(SensorData::Temperature(temperature));
+            tx_sensor.send(SensorData::Pressure(pressure));
+        }
+        tx_sensor.send(SensorData::Light(light_pin.is_low()));
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+/// Calibration reference points for the capacitive soil-moisture sensor, persisted in NVS so they
+/// survive a reboot
+struct MoistureCalibration {
+    nvs: EspNvs<NvsDefault>,
+    dry_raw: u16,
+    wet_raw: u16,
+}
+
+impl MoistureCalibration {
+    const NAMESPACE: &'static str = "soil_moisture";
+    const KEY_DRY: &'static str = "dry_raw";
+    const KEY_WET: &'static str = "wet_raw";
+
+    fn load(partition: EspDefaultNvsPartition) -> Self {
+        let nvs = EspNvs::new(partition, Self::NAMESPACE, true).unwrap();
+        let dry_raw = nvs.get_u16(Self::KEY_DRY).unwrap().unwrap_or(2800); // in air: high raw reading
+        let wet_raw = nvs.get_u16(Self::KEY_WET).unwrap().unwrap_or(1200); // in water: low raw reading
+        MoistureCalibration { nvs, dry_raw, wet_raw }
+    }
+
+    fn capture_dry(&mut self, raw: u16) {
+        self.dry_raw = raw;
+        self.nvs.set_u16(Self::KEY_DRY, raw).unwrap();
+    }
+
+    fn capture_wet(&mut self, raw: u16) {
+        self.wet_raw = raw;
+        self.nvs.set_u16(Self::KEY_WET, raw).unwrap();
+    }
+
+    /// Map a raw ADC reading to 0-100% using the two stored reference points, clamped to range
+    fn to_percent(&self, raw: u16) -> f32 {
+        let (dry, wet) = (self.dry_raw as f32, self.wet_raw as f32);
+        let percent = (dry - raw as f32) / (dry - wet) * 100.0;
+        percent.clamp(0.0, 100.0)
+    }
+}
+
+/// Read the capacitive soil-moisture sensor over ADC, mapping the raw reading to a percentage with
+/// the stored calibration, and apply any dry/wet calibration capture commands from `display_task`
+fn moisture_task(tx_sensor: Broadcaster<SensorData>, rx_calibrate: mpsc::Receiver<CalibrationCommand>,
+    calibration_mutex: Arc<Mutex<MoistureCalibration>>, adc: AdcDriver<'static, ADC2>, pin: Gpio13)
+{
+    let mut adc_pin = AdcChannelDriver::new(&adc, pin, &AdcChannelConfig::new()).unwrap();
+    loop {
+        let raw = adc_pin.read().unwrap();
+        while let Ok(command) = rx_calibrate.try_recv() {
+            let mut calibration = calibration_mutex.lock().unwrap();
+            match command {
+                CalibrationCommand::CaptureDry => { calibration.capture_dry(raw); log::info!("[!] Captured dry (air) calibration point: {raw}"); },
+                CalibrationCommand::CaptureWet => { calibration.capture_wet(raw); log::info!("[!] Captured wet (water) calibration point: {raw}"); },
+            }
+        }
+        let percent = calibration_mutex.lock().unwrap().to_percent(raw);
+        tx_sensor.send(SensorData::Moisture(percent));
+        thread::sleep(Duration::from_millis(500));
+    }
+}
+
+/// Bit-banged OneWire master driven directly on a single open-drain pin, used for the DS18B20
+/// probes. All timings are from the DS18B20 datasheet.
+struct OneWireBus {
+    pin: PinDriver<'static, AnyIOPin, InputOutput>,
+}
+
+impl OneWireBus {
+    fn new(pin: PinDriver<'static, AnyIOPin, InputOutput>) -> Self {
+        OneWireBus { pin }
+    }
+
+    /// Reset pulse: drive low 480us, release, then sample for a presence pulse after 70us
+    fn reset(&mut self) -> bool {
+        self.pin.set_low().unwrap();
+        Ets::delay_us(480);
+        self.pin.set_high().unwrap();
+        Ets::delay_us(70);
+        let present = self.pin.is_low();
+        Ets::delay_us(410);
+        present
+    }
+
+    fn write_byte(&mut self, mut byte: u8) {
+        for _ in 0..8 {
+            self.write_bit(byte & 0x01 == 1);
+            byte >>= 1;
+        }
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = 0u8;
+        for i in 0..8 {
+            if self.read_bit() { byte |= 1 << i; }
+        }
+        byte
+    }
+}
+
+/// The two 1-Wire primitives the Search-ROM algorithm drives. Pulled out as a trait so the
+/// bit-level algorithm in `search_rom_pass` can run against a simulated bus in tests instead
+/// of only against real pin timings.
+trait OneWireIo {
+    fn read_bit(&mut self) -> bool;
+    fn write_bit(&mut self, bit: bool);
+}
+
+impl OneWireIo for OneWireBus {
+    fn read_bit(&mut self) -> bool {
+        self.pin.set_low().unwrap();
+        Ets::delay_us(2);
+        self.pin.set_high().unwrap();
+        Ets::delay_us(13);
+        let bit = self.pin.is_high();
+        Ets::delay_us(45);
+        bit
+    }
+
+    fn write_bit(&mut self, bit: bool) {
+        self.pin.set_low().unwrap();
+        if bit {
+            Ets::delay_us(6);
+            self.pin.set_high().unwrap();
+            Ets::delay_us(54);
+        } else {
+            Ets::delay_us(60);
+            self.pin.set_high().unwrap();
+            Ets::delay_us(6);
+        }
+    }
+}
+
+/// Run a single Search-ROM pass (one bus reset's worth of the 0xF0 algorithm) against any
+/// `OneWireIo`. Returns the new `last_discrepancy` to feed into the next pass, or `None` if no
+/// device responded (bus error / no devices present).
+fn search_rom_pass(last_discrepancy: i32, rom: &mut [u8; 8], io: &mut impl OneWireIo) -> Option<i32> {
+    let mut discrepancy = -1;
+    for bit_i in 0..64 {
+        let bit = io.read_bit();
+        let complement = io.read_bit();
+        let direction = if bit && complement {
+            // no devices responded
+            return None;
+        } else if bit != complement {
+            // all remaining devices agree; no choice was made, so this is not a discrepancy
+            bit
+        } else {
+            // discrepancy: devices disagree on this bit
+            let dir = if (bit_i as i32) < last_discrepancy {
+                // replay the earlier pass's choice
+                (rom[bit_i / 8] >> (bit_i % 8)) & 0x01 == 1
+            } else if (bit_i as i32) == last_discrepancy {
+                // this is the bit where the previous pass took the 0 branch; take 1 this time
+                true
+            } else {
+                // newly reached discrepancy bit: take the 0 branch first
+                false
+            };
+            // Track the last bit position where we took the 0 branch, whether that choice
+            // came from a fresh discrepancy or from replaying an unresolved earlier branch.
+            if !dir {
+                discrepancy = bit_i as i32;
+            }
+            dir
+        };
+        if direction {
+            rom[bit_i / 8] |= 1 << (bit_i % 8);
+        } else {
+            rom[bit_i / 8] &= !(1 << (bit_i % 8));
+        }
+        io.write_bit(direction);
+    }
+    Some(discrepancy)
+}
+
+impl OneWireBus {
+    /// Enumerate every ROM on the bus using the Search-ROM (0xF0) algorithm: at each of the 64 bits
+    /// read the bit and its complement, following `last_discrepancy` to eventually visit them all.
+    fn search_roms(&mut self) -> Vec<[u8; 8]> {
+        let mut roms = Vec::new();
+        // Persists across passes: each pass only re-decides bits from the last
+        // discrepancy onward and replays the previously resolved bits below it.
+        let mut rom = [0u8; 8];
+        let mut last_discrepancy: i32 = -1;
+        loop {
+            if !self.reset() { break; }
+            self.write_byte(0xF0);
+            match search_rom_pass(last_discrepancy, &mut rom, self) {
+                None => break,
+                Some(discrepancy) => {
+                    roms.push(rom);
+                    last_discrepancy = discrepancy;
+                    if last_discrepancy == -1 { break; }
+                }
+            }
+        }
+        roms
     }
-}
 
+    fn match_rom(&mut self, rom: &[u8; 8]) {
+        self.write_byte(0x55);
+        for byte in rom { self.write_byte(*byte); }
+    }
 
-/// Send ButtonEvents
-fn button_task(tx_button: mpsc::Sender<ButtonEvent>, btn_pin: PinDriver<'static, AnyIOPin, Input>) {
-    loop {
-        if btn_pin.is_low() {
-            let press_event = determine_press_type(&btn_pin);
-            tx_button.send(press_event).unwrap();
-            std::thread::sleep(Duration::from_millis(300)); // stop listening for 300ms
+    /// CRC8 with polynomial x^8+x^5+x^4+1, as used for the DS18B20 scratchpad
+    fn crc8(bytes: &[u8]) -> u8 {
+        let mut crc = 0u8;
+        for &byte in bytes {
+            let mut b = byte;
+            for _ in 0..8 {
+                let mix = (crc ^ b) & 0x01;
+                crc >>= 1;
+                if mix == 1 { crc ^= 0x8C; }
+                b >>= 1;
+            }
         }
-        std::thread::sleep(Duration::from_millis(100)); // check every 100ms to be reactive
+        crc
+    }
+
+    /// Start a conversion on every device at once (Skip-ROM + Convert-T) and, after the 12-bit
+    /// conversion time has elapsed, read back each probe's temperature (Match-ROM + Read-Scratchpad)
+    fn read_all_temperatures(&mut self, roms: &[[u8; 8]]) -> Vec<Option<f32>> {
+        self.reset();
+        self.write_byte(0xCC); // Skip-ROM
+        self.write_byte(0x44); // Convert-T
+        std::thread::sleep(Duration::from_millis(750));
+
+        roms.iter().map(|rom| {
+            self.reset();
+            self.match_rom(rom);
+            self.write_byte(0xBE); // Read-Scratchpad
+            let mut scratchpad = [0u8; 9];
+            for byte in scratchpad.iter_mut() { *byte = self.read_byte(); }
+            if Self::crc8(&scratchpad[..8]) != scratchpad[8] { return None; }
+            let raw = ((scratchpad[1] as i16) << 8) | scratchpad[0] as i16;
+            Some(raw as f32 / 16.0)
+        }).collect()
     }
 }
 
-/// Read the RTC every second and send event to the display task
-fn rtc_task(tx_rtc: mpsc::Sender<RTCEvent>, rtc_mutex: Arc<Mutex<RTCInterface>>) {
+/// Convert Temperature, with one or more DS18B20 probes on a shared OneWire bus
+fn ds18b20_task(tx_sensor: Broadcaster<SensorData>, pin: PinDriver<'static, AnyIOPin, InputOutput>) {
+    let mut bus = OneWireBus::new(pin);
+    let roms = bus.search_roms();
+    log::info!("Found {} DS18B20 probe(s) on the OneWire bus", roms.len());
     loop {
-        let mut rtc = rtc_mutex.lock().unwrap();
-        rtc.read();
-        let hour = rtc.hours;
-        let minute = rtc.minutes;
-        let second = rtc.seconds;
-        tx_rtc.send(RTCEvent::Tick(hour, minute, second)).unwrap();
-        drop(rtc); // drop the lock
+        for (i, temperature) in bus.read_all_temperatures(&roms).into_iter().enumerate() {
+            if let Some(temperature) = temperature {
+                tx_sensor.send(SensorData::ProbeTemp(i as u8, temperature));
+            }
+        }
         thread::sleep(Duration::from_millis(1000));
     }
 }
 
-/// Read the light level, temperature and moisture, calculate the pressure and send events to the display task
-fn sensor_task(tx_sensor: mpsc::Sender<SensorData>, mut dht11: Dht11<PinDriver<'static, AnyIOPin, InputOutput>>, light_pin: PinDriver<'static, Gpio38, Input>) {
+/// Measure supply voltage through a resistor divider that is only powered while sampling: drive
+/// the enable pin high, let the divider settle, average a few ADC samples, then drive it low again
+fn battery_task(tx_sensor: Broadcaster<SensorData>, mut enable_pin: PinDriver<'static, Gpio11, Output>,
+    adc: AdcDriver<'static, ADC1>, pin: Gpio12)
+{
+    let mut adc_pin = AdcChannelDriver::new(&adc, pin, &AdcChannelConfig::new()).unwrap();
+    loop {
+        enable_pin.set_high().unwrap();
+        Ets::delay_us(200); // let the divider settle before sampling
+        let mut samples = [0u16; 8];
+        for sample in samples.iter_mut() {
+            *sample = adc_pin.read().unwrap();
+        }
+        enable_pin.set_low().unwrap();
+
+        let average_mv = samples.iter().map(|&s| s as u32).sum::<u32>() / samples.len() as u32;
+        let voltage = (average_mv as f32 / 1000.0) * BATTERY_DIVIDER_RATIO;
+        tx_sensor.send(SensorData::Battery(voltage));
+
+        thread::sleep(Duration::from_secs(10));
+    }
+}
+
+/// Closed-loop heater control: compares the latest `SensorData::Temperature` against the
+/// setpoint in `target_mutex` with `THERMOSTAT_HYSTERESIS_C` of hysteresis (on below target-h,
+/// off above target+h) so the relay doesn't chatter at the boundary. Only flips the output when
+/// a fresh reading arrives; if none shows up within `THERMOSTAT_SENSOR_TIMEOUT` it falls back to
+/// a safe off state rather than keep driving the heater off a reading that may be stale.
+fn thermostat_task(rx_sensor: mpsc::Receiver<SensorData>, tx_thermostat: mpsc::Sender<ThermostatEvent>,
+    target_mutex: Arc<Mutex<f32>>, mut heater_pin: PinDriver<'static, Gpio23, Output>)
+{
+    let mut heater_on = false;
+    let mut last_reading: Option<Instant> = None;
+
     loop {
-        if let Ok(measurement) = dht11.perform_measurement(&mut Ets) {
-            let temperature = measurement.temperature as f32 /10.0;
-            let humidity = measurement.humidity as f32 /10.0;
-            let pressure = get_atm_pressure(temperature, humidity);
-            tx_sensor.send(SensorData::Temperature(temperature)).unwrap();
-            tx_sensor.send(SensorData::Moisture(humidity)).unwrap();
-            tx_sensor.send(SensorData::Pressure(pressure)).unwrap();
+        while let Ok(sensor) = rx_sensor.try_recv() {
+            if let SensorData::Temperature(temperature) = sensor {
+                last_reading = Some(Instant::now());
+                let target = *target_mutex.lock().unwrap();
+                if !heater_on && temperature < target - THERMOSTAT_HYSTERESIS_C {
+                    heater_on = true;
+                } else if heater_on && temperature > target + THERMOSTAT_HYSTERESIS_C {
+                    heater_on = false;
+                }
+            }
         }
-        tx_sensor.send(SensorData::Light(light_pin.is_low())).unwrap();
+        if heater_on && last_reading.is_none_or(|at| at.elapsed() > THERMOSTAT_SENSOR_TIMEOUT) {
+            heater_on = false;
+        }
+
+        if heater_on { heater_pin.set_high().unwrap(); } else { heater_pin.set_low().unwrap(); }
+        tx_thermostat.send(if heater_on { ThermostatEvent::HeaterOn } else { ThermostatEvent::HeaterOff }).unwrap();
+
         thread::sleep(Duration::from_millis(200));
     }
 }
 
+/// Minimal CC1101 driver: register writes for a GFSK, fixed-packet-length configuration on the
+/// 868/915MHz ISM band, and TX-FIFO strobing for transmission
+struct Cc1101<'d> {
+    spi: SpiDeviceDriver<'d, SpiDriver<'d>>,
+    gdo0: PinDriver<'d, AnyIOPin, Input>,
+}
+
+impl<'d> Cc1101<'d> {
+    const SRES: u8 = 0x30; // reset
+    const STX: u8 = 0x35; // enable TX
+    const SFTX: u8 = 0x3B; // flush TX FIFO
+    const TXFIFO_BURST: u8 = 0x7F;
+
+    fn new(spi: SpiDeviceDriver<'d, SpiDriver<'d>>, gdo0: PinDriver<'d, AnyIOPin, Input>) -> Self {
+        Cc1101 { spi, gdo0 }
+    }
+
+    fn strobe(&mut self, command: u8) {
+        let mut buf = [command];
+        self.spi.transfer_in_place(&mut buf).unwrap();
+    }
+
+    fn write_reg(&mut self, addr: u8, value: u8) {
+        let mut buf = [addr, value];
+        self.spi.transfer_in_place(&mut buf).unwrap();
+    }
+
+    /// Configure GFSK, fixed packet length, the chosen band, and the node's address/sync word
+    fn init(&mut self, sync_word: [u8; 2], node_address: u8, packet_len: u8) {
+        self.strobe(Self::SRES);
+        self.write_reg(0x02, 0x06); // IOCFG0: GDO0 asserts on sync word, deasserts at packet end
+        self.write_reg(0x04, sync_word[0]); // SYNC1
+        self.write_reg(0x05, sync_word[1]); // SYNC0
+        self.write_reg(0x06, packet_len); // PKTLEN: fixed length
+        self.write_reg(0x07, 0x04); // PKTCTRL1: append RSSI/LQI status bytes
+        self.write_reg(0x08, 0x00); // PKTCTRL0: fixed packet length, no whitening
+        self.write_reg(0x09, node_address); // ADDR
+        // 915MHz: FREQ = f_carrier * 2^16 / f_xosc(26MHz)
+        self.write_reg(0x0D, 0x23); // FREQ2
+        self.write_reg(0x0E, 0x31); // FREQ1
+        self.write_reg(0x0F, 0x3B); // FREQ0
+        self.write_reg(0x10, 0x5B); // MDMCFG4: channel bandwidth / data rate exponent
+        self.write_reg(0x11, 0xF8); // MDMCFG3: data rate mantissa
+        self.write_reg(0x12, 0x12); // MDMCFG2: GFSK, 16/16 sync word bits
+    }
+
+    /// Push a frame into the TX FIFO, strobe STX, and block on GDO0 for transmit-done
+    fn transmit(&mut self, frame: &[u8]) {
+        self.strobe(Self::SFTX); // flush any stale bytes first
+        let mut fifo_write = Vec::with_capacity(frame.len() + 1);
+        fifo_write.push(Self::TXFIFO_BURST);
+        fifo_write.extend_from_slice(frame);
+        self.spi.transfer_in_place(&mut fifo_write).unwrap();
+        self.strobe(Self::STX);
+        while self.gdo0.is_low() {} // wait for the sync word to go out (GDO0 asserts)
+        while self.gdo0.is_high() {} // wait for transmit-done (GDO0 deasserts)
+    }
+}
+
+const RADIO_NODE_ID: u8 = 0x01;
+const RADIO_SYNC_WORD: [u8; 2] = [0xD3, 0x91];
+const RADIO_MIN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Type tag prefixing each value in a telemetry frame, so the base station can decode it
+fn sensor_type_tag(sensor: &SensorData) -> u8 {
+    match sensor {
+        SensorData::Temperature(_) => 0x01,
+        SensorData::Moisture(_) => 0x02,
+        SensorData::Light(_) => 0x03,
+        SensorData::Pressure(_) => 0x04,
+        SensorData::ProbeTemp(_, _) => 0x05,
+        SensorData::Battery(_) => 0x06,
+    }
+}
+
+/// Broadcast sensor readings and clock ticks over the CC1101: [node id, type tag, probe index
+/// (0 for non-probe readings), value as 4 little-endian bytes]. Clock ticks use type tag 0x00
+/// and carry hour, minute and second in the first three value bytes. Each kind of reading is
+/// only sent when it changes or every `RADIO_MIN_INTERVAL`, to conserve airtime
+fn radio_task(rx_sensor: mpsc::Receiver<SensorData>, rx_rtc: mpsc::Receiver<RTCEvent>, mut radio: Cc1101<'static>) {
+    radio.init(RADIO_SYNC_WORD, RADIO_NODE_ID, 7);
+    // last frame sent and when, per (type tag, probe index), so interleaved readings of
+    // different kinds are not compared against each other
+    let mut last_sent: HashMap<(u8, u8), ([u8; 7], Instant)> = HashMap::new();
+
+    let mut maybe_transmit = |radio: &mut Cc1101<'static>, frame: [u8; 7]| {
+        let key = (frame[1], frame[2]);
+        let due = last_sent.get(&key).is_none_or(|(last_frame, at)| frame != *last_frame || at.elapsed() >= RADIO_MIN_INTERVAL);
+        if due {
+            radio.transmit(&frame);
+            last_sent.insert(key, (frame, Instant::now()));
+        }
+    };
+
+    loop {
+        while let Ok(sensor) = rx_sensor.try_recv() {
+            let mut frame = [0u8; 7];
+            frame[0] = RADIO_NODE_ID;
+            frame[1] = sensor_type_tag(&sensor);
+            let value = match sensor {
+                SensorData::Temperature(v) | SensorData::Moisture(v) | SensorData::Pressure(v) | SensorData::Battery(v) => v,
+                SensorData::Light(on) => if on { 1.0 } else { 0.0 },
+                SensorData::ProbeTemp(idx, v) => { frame[2] = idx; v },
+            };
+            frame[3..7].copy_from_slice(&value.to_le_bytes());
+            maybe_transmit(&mut radio, frame);
+        }
+        while let Ok(rtc_event) = rx_rtc.try_recv() {
+            if let RTCEvent::Tick(hour, minute, second) = rtc_event {
+                let frame = [RADIO_NODE_ID, 0x00, 0, hour, minute, second, 0];
+                maybe_transmit(&mut radio, frame);
+            }
+            // RTCEvent::Date isn't transmitted yet; the telemetry frame format has no field for it
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
 /*
 // HC-SR04 ultrasonic distance sensor (no crate)
 fn main() {
@@ -562,39 +1557,397 @@ fn main() {
 }
 */
 
+/*
+// Interrupt-driven scheduler (alternative to the std::thread + mpsc task model above)
+//
+// ESP-IDF runs on top of FreeRTOS rather than bare-metal Cortex-M, so `rtic` itself doesn't
+// apply here, but the same idea - priority-ceiling resources instead of a mutex any thread can
+// block on - carries over: the button is serviced purely from its GPIO ISR (no `button_task`
+// polling loop), the RTC tick and sensor sampling are periodic callbacks off one hardware timer,
+// and `display_task` becomes the idle-priority work done in `loop {}` once everything higher has
+// had a chance to run. Shared state lives in `Resources`, guarded by a FreeRTOS critical section
+// (`esp_idf_hal::interrupt::free`) that's only ever held for a few field reads/writes, never
+// across a blocking call the way `Arc<Mutex<RTCInterface>>` can be today.
+//
+// This sketch does not compile as written: `SetupMode::next_field`, `RTCInterface::read_time`,
+// `read_dht11_temperature` and `render_display` are not defined in this file, and the real
+// `RTCInterface::new` takes pin drivers and a `Delay`.
+struct Resources {
+    rtc: RTCInterface,
+    latest: HashMap<&'static str, SensorData>,
+    setup_mode: SetupMode,
+    full_access_mode: bool,
+}
+
+fn main() {
+    esp_idf_svc::sys::link_patches();
+    EspLogger::initialize_default();
+
+    let peripherals = Peripherals::take().unwrap();
+    let resources: &'static Mutex<Resources> = Box::leak(Box::new(Mutex::new(Resources {
+        rtc: RTCInterface::new(peripherals.pins.gpio1, peripherals.pins.gpio2, peripherals.pins.gpio3),
+        latest: HashMap::new(),
+        setup_mode: SetupMode::Idle,
+        full_access_mode: false,
+    })));
+
+    // Button: the ISR itself advances the setup field on every edge (highest priority, shortest
+    // critical section); it does not tell ShortPress/LongPress/DoublePress apart.
+    let mut btn_pin = PinDriver::input(peripherals.pins.gpio21).unwrap();
+    btn_pin.set_interrupt_type(InterruptType::AnyEdge).unwrap();
+    unsafe {
+        btn_pin.subscribe(move || {
+            esp_idf_hal::interrupt::free(|| {
+                let mut res = resources.lock().unwrap();
+                res.setup_mode = res.setup_mode.next_field();
+            });
+        }).unwrap();
+    }
+    btn_pin.enable_interrupt().unwrap();
+
+    // RTC tick + sensor sampling: one hardware timer fires every second at a priority below the
+    // button ISR but above the display loop, so a button press is never delayed by a DS1302
+    // bit-bang transfer or a DHT11 read.
+    let timer_config = esp_idf_hal::timer::config::Config::new().auto_reload(true);
+    let mut timer = esp_idf_hal::timer::TimerDriver::new(peripherals.timer00, &timer_config).unwrap();
+    timer.set_alarm(timer.tick_hz()).unwrap(); // once per second
+    unsafe {
+        timer.subscribe(move || {
+            esp_idf_hal::interrupt::free(|| {
+                let mut res = resources.lock().unwrap();
+                let (hour, minute, second) = res.rtc.read_time();
+                let temperature = read_dht11_temperature();
+                res.latest.insert("temperature", SensorData::Temperature(temperature));
+                log::info!("tick {:02}:{:02}:{:02}", hour, minute, second);
+            });
+        }).unwrap();
+    }
+    timer.enable_interrupt().unwrap();
+    timer.enable_alarm(true).unwrap();
+    timer.enable(true).unwrap();
+
+    // Display: lowest priority, runs whenever nothing above it needs the CPU. It only ever reads
+    // `Resources` for the length of a clone, so it never holds the critical section across the
+    // slow I2C transfer that actually pushes pixels to the OLED.
+    loop {
+        let snapshot = esp_idf_hal::interrupt::free(|| {
+            let res = resources.lock().unwrap();
+            (res.setup_mode, res.full_access_mode, res.latest.get("temperature").copied())
+        });
+        render_display(snapshot);
+    }
+}
+ */
+
+/// Wraps an `embedded-graphics` `DrawTarget` so `display_task` can render without knowing which
+/// panel is attached. Only monochrome targets (`Color = BinaryColor`, e.g. the SSD1306 OLED) fit
+/// the bound; color panels such as an ST7789 TFT (Rgb565) do not.
+struct Screen<D: DrawTarget<Color = BinaryColor>> {
+    target: D,
+    text_style: MonoTextStyle<'static, BinaryColor>,
+}
+
+impl<D: DrawTarget<Color = BinaryColor>> Screen<D> {
+    fn new(target: D) -> Self {
+        Screen { target, text_style: MonoTextStyle::new(&FONT_6X10, BinaryColor::On) }
+    }
+
+    /// Draw a line of text, optionally inverted (white background/black text) to highlight
+    /// the field currently being edited in `SetupMode`.
+    fn draw_line(&mut self, y: i32, text: &str, highlighted: bool) {
+        if highlighted {
+            let _ = Rectangle::new(Point::new(0, y - 8), Size::new(128, 10))
+                .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+                .draw(&mut self.target);
+            let style = MonoTextStyle::new(&FONT_6X10, BinaryColor::Off);
+            let _ = Text::new(text, Point::new(0, y), style).draw(&mut self.target);
+        } else {
+            let _ = Text::new(text, Point::new(0, y), self.text_style).draw(&mut self.target);
+        }
+    }
+
+    fn draw_clock(&mut self, hour: u8, minute: u8, second: u8, setup_mode: &SetupMode) {
+        let line = format!("{:02}:{:02}:{:02}", hour, minute, second);
+        self.draw_line(10, &line, false);
+        // underline the field currently selected for editing
+        let highlight = match setup_mode {
+            SetupMode::Hours => Some((0, 2)),
+            SetupMode::Minutes => Some((3, 5)),
+            SetupMode::Seconds => Some((6, 8)),
+            SetupMode::Idle | SetupMode::Date | SetupMode::Month | SetupMode::Year
+                | SetupMode::AlarmHour | SetupMode::AlarmMinute | SetupMode::Target
+                | SetupMode::CalibrateDry | SetupMode::CalibrateWet => None,
+        };
+        if let Some((start, end)) = highlight {
+            let x = start as i32 * 6;
+            let width = (end - start) as i32 * 6;
+            let _ = Rectangle::new(Point::new(x, 12), Size::new(width as u32, 1))
+                .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+                .draw(&mut self.target);
+        }
+    }
+
+    /// Draw the calendar date, highlighting the whole line while a Date/Month/Year field is
+    /// being edited (unlike `draw_clock` there's no sub-field underline here, just invert)
+    fn draw_date(&mut self, date: u8, month: u8, day: u8, year: u8, setup_mode: &SetupMode) {
+        let weekday = match day {
+            1 => "Mon", 2 => "Tue", 3 => "Wed", 4 => "Thu", 5 => "Fri", 6 => "Sat", 7 => "Sun",
+            _ => "???",
+        };
+        let line = format!("{weekday} {:02}/{:02}/20{:02}", date, month, year);
+        let highlighted = matches!(setup_mode, SetupMode::Date | SetupMode::Month | SetupMode::Year);
+        self.draw_line(62, &line, highlighted);
+    }
+
+    /// `thermostat`, when `Some((target_c, heater_on))`, is appended to the line; `display_task`
+    /// only passes it while `name` is "temperature", so the setpoint and heater state are shown
+    /// alongside the measured temperature rather than on every sensor
+    fn draw_sensor(&mut self, name: &str, value: Option<&SensorData>, thermostat: Option<(f32, bool)>) {
+        let mut line = match value {
+            Some(SensorData::Temperature(t)) => format!("{name}: {t:.1}C"),
+            Some(SensorData::Moisture(h)) => format!("{name}: {h:.1}%"),
+            Some(SensorData::Light(b)) => format!("{name}: {}", if *b { "Bright" } else { "Dark" }),
+            Some(SensorData::Pressure(p)) => format!("{name}: {p:.1}hPa"),
+            Some(SensorData::ProbeTemp(idx, t)) => format!("{name}[{idx}]: {t:.1}C"),
+            Some(SensorData::Battery(v)) => format!("{name}: {v:.2}V"),
+            None => format!("{name}: N/A"),
+        };
+        if let Some((target_c, heater_on)) = thermostat {
+            line.push_str(&format!(" set:{:.1}C heat:{}", target_c, if heater_on { "ON" } else { "OFF" }));
+        }
+        self.draw_line(24, &line, false);
+    }
+
+    /// `alarm` is the currently programmed (hour, minute), shown while it's being edited or
+    /// ringing; `alarm_ringing` takes over the whole line until the alarm is dismissed. `target_c`
+    /// is the thermostat setpoint, shown while `SetupMode::Target` is being edited.
+    fn draw_status(&mut self, full_access_mode: bool, can_be_unlocked: bool, setup_mode: &SetupMode, alarm: (u8, u8), alarm_ringing: bool, target_c: f32) {
+        let line = if alarm_ringing {
+            format!("ALARM {:02}:{:02} !!!", alarm.0, alarm.1)
+        } else {
+            match setup_mode {
+                SetupMode::CalibrateDry => "CALIBRATE: DRY (air)".to_string(),
+                SetupMode::CalibrateWet => "CALIBRATE: WET (water)".to_string(),
+                SetupMode::Date => "SET DATE".to_string(),
+                SetupMode::Month => "SET MONTH".to_string(),
+                SetupMode::Year => "SET YEAR".to_string(),
+                SetupMode::AlarmHour => format!("SET ALARM HOUR: {:02}", alarm.0),
+                SetupMode::AlarmMinute => format!("SET ALARM MIN: {:02}", alarm.1),
+                SetupMode::Target => format!("SET TARGET: {:.1}C", target_c),
+                _ => if full_access_mode { "FULL ACCESS".to_string() } else if can_be_unlocked { "UNLOCKABLE".to_string() } else { "RESTRICTED".to_string() },
+            }
+        };
+        self.draw_line(38, &line, false);
+    }
+
+    fn draw_probes(&mut self, probes: &HashMap<u8, f32>) {
+        let mut line = String::new();
+        let mut indices: Vec<&u8> = probes.keys().collect();
+        indices.sort();
+        for idx in indices {
+            line.push_str(&format!("P{idx}:{:.1}C ", probes[idx]));
+        }
+        self.draw_line(50, &line, false);
+    }
+}
+
+/// Capability to push a buffered `DrawTarget`'s framebuffer out to real hardware; implemented
+/// for the ssd1306 buffered-graphics mode used in `main` so `Screen` stays generic over panel
+/// type while still being able to flush when one is actually attached
+trait FlushTarget {
+    fn flush_target(&mut self);
+}
+
+impl<DI, SIZE> FlushTarget for Ssd1306<DI, SIZE, ssd1306::mode::BufferedGraphicsMode<SIZE>>
+where
+    DI: ssd1306::prelude::WriteOnlyDataCommand,
+    SIZE: ssd1306::prelude::DisplaySize,
+{
+    fn flush_target(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// A `display_task` render backend: either the OLED-backed `Screen` or the headless `LogDisplay`
+/// below, so the task itself doesn't care which one it's driving
+trait Display {
+    fn clear(&mut self);
+    fn draw_clock(&mut self, hour: u8, minute: u8, second: u8, setup_mode: &SetupMode);
+    fn draw_date(&mut self, date: u8, month: u8, day: u8, year: u8, setup_mode: &SetupMode);
+    fn draw_sensor(&mut self, name: &str, value: Option<&SensorData>, thermostat: Option<(f32, bool)>);
+    fn draw_status(&mut self, full_access_mode: bool, can_be_unlocked: bool, setup_mode: &SetupMode, alarm: (u8, u8), alarm_ringing: bool, target_c: f32);
+    fn draw_probes(&mut self, probes: &HashMap<u8, f32>);
+    fn present(&mut self);
+}
+
+impl<D: DrawTarget<Color = BinaryColor> + FlushTarget> Display for Screen<D> {
+    fn clear(&mut self) { let _ = self.target.clear(BinaryColor::Off); }
+    fn draw_clock(&mut self, hour: u8, minute: u8, second: u8, setup_mode: &SetupMode) { Screen::draw_clock(self, hour, minute, second, setup_mode); }
+    fn draw_date(&mut self, date: u8, month: u8, day: u8, year: u8, setup_mode: &SetupMode) { Screen::draw_date(self, date, month, day, year, setup_mode); }
+    fn draw_sensor(&mut self, name: &str, value: Option<&SensorData>, thermostat: Option<(f32, bool)>) { Screen::draw_sensor(self, name, value, thermostat); }
+    fn draw_status(&mut self, full_access_mode: bool, can_be_unlocked: bool, setup_mode: &SetupMode, alarm: (u8, u8), alarm_ringing: bool, target_c: f32) { Screen::draw_status(self, full_access_mode, can_be_unlocked, setup_mode, alarm, alarm_ringing, target_c); }
+    fn draw_probes(&mut self, probes: &HashMap<u8, f32>) { Screen::draw_probes(self, probes); }
+    fn present(&mut self) { self.target.flush_target(); }
+}
+
+/// Headless `Display` backend that mirrors every draw call as a `log::info!` line, matching the
+/// pre-OLED behavior, for running the state machine with only a serial console attached
+struct LogDisplay;
+
+impl Display for LogDisplay {
+    fn clear(&mut self) {}
+
+    fn draw_clock(&mut self, hour: u8, minute: u8, second: u8, setup_mode: &SetupMode) {
+        log::info!("==================== Time: {:02}:{:02}:{:02} ====================", hour, minute, second);
+        match setup_mode {
+            SetupMode::Hours => log::info!("[RTC SETUP MODE] Selected: Hours"),
+            SetupMode::Minutes => log::info!("[RTC SETUP MODE] Selected: Minutes"),
+            SetupMode::Seconds => log::info!("[RTC SETUP MODE] Selected: Seconds"),
+            SetupMode::Date => log::info!("[RTC SETUP MODE] Selected: Date"),
+            SetupMode::Month => log::info!("[RTC SETUP MODE] Selected: Month"),
+            SetupMode::Year => log::info!("[RTC SETUP MODE] Selected: Year"),
+            SetupMode::AlarmHour => log::info!("[RTC SETUP MODE] Selected: Alarm Hour"),
+            SetupMode::AlarmMinute => log::info!("[RTC SETUP MODE] Selected: Alarm Minute"),
+            SetupMode::Target => log::info!("[RTC SETUP MODE] Selected: Thermostat Target"),
+            SetupMode::CalibrateDry | SetupMode::CalibrateWet | SetupMode::Idle => {},
+        }
+    }
+
+    fn draw_date(&mut self, date: u8, month: u8, day: u8, year: u8, _setup_mode: &SetupMode) {
+        let weekday = match day {
+            1 => "Mon", 2 => "Tue", 3 => "Wed", 4 => "Thu", 5 => "Fri", 6 => "Sat", 7 => "Sun",
+            _ => "???",
+        };
+        log::info!("[DATE] {weekday} {:02}/{:02}/20{:02}", date, month, year);
+    }
+
+    fn draw_sensor(&mut self, name: &str, value: Option<&SensorData>, thermostat: Option<(f32, bool)>) {
+        match value {
+            Some(SensorData::Temperature(t)) => log::info!("{name}: {t:.1}C"),
+            Some(SensorData::Moisture(h)) => log::info!("{name}: {h:.1}%"),
+            Some(SensorData::Light(b)) => log::info!("{name}: {}", if *b { "Bright" } else { "Dark" }),
+            Some(SensorData::Pressure(p)) => log::info!("{name}: {p:.1}hPa"),
+            Some(SensorData::ProbeTemp(idx, t)) => log::info!("{name}[{idx}]: {t:.1}C"),
+            Some(SensorData::Battery(v)) => log::info!("{name}: {v:.2}V"),
+            None => log::info!("{name}: N/A"),
+        }
+        if let Some((target_c, heater_on)) = thermostat {
+            log::info!("[THERMOSTAT] set:{:.1}C heat:{}", target_c, if heater_on { "ON" } else { "OFF" });
+        }
+    }
+
+    fn draw_status(&mut self, full_access_mode: bool, can_be_unlocked: bool, setup_mode: &SetupMode, alarm: (u8, u8), alarm_ringing: bool, target_c: f32) {
+        if alarm_ringing {
+            log::info!("[STATUS] ALARM {:02}:{:02} !!!", alarm.0, alarm.1);
+            return;
+        }
+        let line = match setup_mode {
+            SetupMode::CalibrateDry => "CALIBRATE: DRY (air)".to_string(),
+            SetupMode::CalibrateWet => "CALIBRATE: WET (water)".to_string(),
+            SetupMode::Date => "SET DATE".to_string(),
+            SetupMode::Month => "SET MONTH".to_string(),
+            SetupMode::Year => "SET YEAR".to_string(),
+            SetupMode::AlarmHour => format!("SET ALARM HOUR: {:02}", alarm.0),
+            SetupMode::AlarmMinute => format!("SET ALARM MIN: {:02}", alarm.1),
+            SetupMode::Target => format!("SET TARGET: {:.1}C", target_c),
+            _ => if full_access_mode { "FULL ACCESS".to_string() } else if can_be_unlocked { "UNLOCKABLE".to_string() } else { "RESTRICTED".to_string() },
+        };
+        log::info!("[STATUS] {line}");
+    }
+
+    fn draw_probes(&mut self, probes: &HashMap<u8, f32>) {
+        if probes.is_empty() { return; }
+        let mut indices: Vec<&u8> = probes.keys().collect();
+        indices.sort();
+        let line: String = indices.iter().map(|idx| format!("P{idx}:{:.1}C ", probes[idx])).collect();
+        log::info!("[PROBES] {line}");
+    }
+
+    fn present(&mut self) {}
+}
+
 /// Listen on the sensors, button, and RTC channels and display all the data
-fn display_task(rx_sensor: mpsc::Receiver<SensorData>, rx_button: mpsc::Receiver<ButtonEvent>, rx_rtc: mpsc::Receiver<RTCEvent>,
-    mut red_pin: PinDriver<'static, Gpio5, Output>, mut yellow_pin: PinDriver<'static, Gpio6, Output>, mut green_pin: PinDriver<'static, Gpio7, Output>, rtc_mutex: Arc<Mutex<RTCInterface>>)
+fn display_task<S: Display>(rx_sensor: mpsc::Receiver<SensorData>, rx_button: mpsc::Receiver<ButtonEvent>, rx_rtc: mpsc::Receiver<RTCEvent>, rx_rotate: mpsc::Receiver<InputEvent>, rx_mode: mpsc::Receiver<ModeCommand>, rx_alarm: mpsc::Receiver<AlarmEvent>, rx_thermostat: mpsc::Receiver<ThermostatEvent>,
+    mut red_pin: PinDriver<'static, Gpio5, Output>, mut yellow_pin: PinDriver<'static, Gpio6, Output>, mut green_pin: PinDriver<'static, Gpio7, Output>, mut buzzer_pin: PinDriver<'static, Gpio22, Output>, rtc_mutex: Arc<Mutex<RTCInterface>>,
+    tx_calibrate: mpsc::Sender<CalibrationCommand>, target_mutex: Arc<Mutex<f32>>, mut screen: S)
 {
     let mut full_access_mode = false;
     let mut can_be_unlocked = false;
     let mut setup_mode = SetupMode::Idle;
+    let mut last_tick = (0u8, 0u8, 0u8);
+    let mut last_date = (1u8, 1u8, 1u8, 0u8); // (day of month, month, day of week, year)
+    // the programmed alarm time, cached here so editing it doesn't need a RAM round-trip on
+    // every redraw; loaded once from the RTC's battery-backed RAM at startup
+    let mut alarm: (u8, u8) = rtc_mutex.lock().unwrap().get_alarm().unwrap_or((7, 0));
+    let mut alarm_ringing = false;
+    // the thermostat setpoint, cached here like `alarm` so editing it doesn't need a lock on
+    // every redraw; loaded once from the shared setpoint at startup
+    let mut target_c: f32 = *target_mutex.lock().unwrap();
+    let mut heater_on = false;
 
     // store data in a hashmap
     let mut latest_data: HashMap<&'static str, SensorData> = HashMap::new();
+    let mut latest_probes: HashMap<u8, f32> = HashMap::new();
+    let mut latest_battery: Option<f32> = None;
+    let mut blink_state = false;
     let sensor_order = ["temperature", "moisture", "light", "pressure"];
     let mut current_sensor_i: usize = 0;
+    // quarter-steps accumulated from `encoder_task` since the last full detent; reset whenever
+    // the selected field changes so a partial turn on one field never bleeds into the next
+    let mut rotate_accumulator: i32 = 0;
 
     loop {
         // receive from RTC
         while let Ok(rtc_event) = rx_rtc.try_recv() {
-            let RTCEvent::Tick(hour, minute, second) = rtc_event;
-            log::info!("==================== Time: {:02}:{:02}:{:02} ====================", hour, minute, second);
-            can_be_unlocked = minute % 2 == 1;
+            match rtc_event {
+                RTCEvent::Tick(hour, minute, second) => {
+                    last_tick = (hour, minute, second);
+                    can_be_unlocked = minute % 2 == 1;
+                },
+                RTCEvent::Date(date, month, day, year) => {
+                    last_date = (date, month, day, year);
+                },
+            }
         }
         // receive from button
         while let Ok(button_event) = rx_button.try_recv() {
+            // a ringing alarm captures the next button press of any kind to silence it, instead
+            // of letting it fall through to the usual setup-mode/navigation handling
+            if alarm_ringing {
+                alarm_ringing = false;
+                continue;
+            }
             match button_event {
                 // for navigating the sensors data or switching/incrementing time fields
                 ButtonEvent::ShortPress => {
                     if setup_mode != SetupMode::Idle {
-                        // if setup mode => ShortPress increments the current time field
-                        let mut rtc = rtc_mutex.lock().unwrap();
+                        // if setup mode => ShortPress increments the current field (or, for the
+                        // calibration steps, captures the current soil-moisture reading)
                         match setup_mode {
-                            SetupMode::Hours => { rtc.iterate_hour(); log::info!("[!] Incremented Hours"); },
-                            SetupMode::Minutes => { rtc.iterate_minute(); log::info!("[!] Incremented Minutes"); },
-                            SetupMode::Seconds => { rtc.iterate_second(); log::info!("[!] Incremented Seconds"); },
-                            _ => {}
+                            SetupMode::Hours => { rtc_mutex.lock().unwrap().iterate_hour(); log::info!("[!] Incremented Hours"); },
+                            SetupMode::Minutes => { rtc_mutex.lock().unwrap().iterate_minute(); log::info!("[!] Incremented Minutes"); },
+                            SetupMode::Seconds => { rtc_mutex.lock().unwrap().iterate_second(); log::info!("[!] Incremented Seconds"); },
+                            SetupMode::Date => { rtc_mutex.lock().unwrap().iterate_date(); log::info!("[!] Incremented Date"); },
+                            SetupMode::Month => { rtc_mutex.lock().unwrap().iterate_month(); log::info!("[!] Incremented Month"); },
+                            SetupMode::Year => { rtc_mutex.lock().unwrap().iterate_year(); log::info!("[!] Incremented Year"); },
+                            SetupMode::AlarmHour => {
+                                alarm.0 = (alarm.0 + 1) % 24;
+                                rtc_mutex.lock().unwrap().set_alarm(alarm.0, alarm.1);
+                                log::info!("[!] Incremented Alarm Hour");
+                            },
+                            SetupMode::AlarmMinute => {
+                                alarm.1 = (alarm.1 + 1) % 60;
+                                rtc_mutex.lock().unwrap().set_alarm(alarm.0, alarm.1);
+                                log::info!("[!] Incremented Alarm Minute");
+                            },
+                            SetupMode::Target => {
+                                target_c = (target_c + TARGET_STEP_C).min(TARGET_MAX_C);
+                                *target_mutex.lock().unwrap() = target_c;
+                                log::info!("[!] Incremented Thermostat Target");
+                            },
+                            SetupMode::CalibrateDry => { tx_calibrate.send(CalibrationCommand::CaptureDry).unwrap(); },
+                            SetupMode::CalibrateWet => { tx_calibrate.send(CalibrationCommand::CaptureWet).unwrap(); },
+                            SetupMode::Idle => {}
                         }
                     }
                     else {
@@ -602,14 +1955,24 @@ fn display_task(rx_sensor: mpsc::Receiver<SensorData>, rx_button: mpsc::Receiver
                         current_sensor_i = (current_sensor_i+1)%sensor_order.len();
                     }
                 },
-                // for entering/exiting setup mode and looping between time fields
+                // for entering/exiting setup mode and looping through the time, date, alarm, thermostat and calibration fields
                 ButtonEvent::LongPress => {
                     setup_mode = match setup_mode {
                         SetupMode::Idle => { log::info!("[!] Entering Setup Mode..."); SetupMode::Hours },
                         SetupMode::Hours => SetupMode::Minutes,
                         SetupMode::Minutes => SetupMode::Seconds,
-                        SetupMode::Seconds => { log::info!("[!] Exiting Setup Mode..."); SetupMode::Idle },
-                    }
+                        SetupMode::Seconds => SetupMode::Date,
+                        SetupMode::Date => SetupMode::Month,
+                        SetupMode::Month => SetupMode::Year,
+                        SetupMode::Year => SetupMode::AlarmHour,
+                        SetupMode::AlarmHour => SetupMode::AlarmMinute,
+                        SetupMode::AlarmMinute => SetupMode::Target,
+                        SetupMode::Target => SetupMode::CalibrateDry,
+                        SetupMode::CalibrateDry => SetupMode::CalibrateWet,
+                        SetupMode::CalibrateWet => { log::info!("[!] Exiting Setup Mode..."); SetupMode::Idle },
+                    };
+                    // the encoder's partial turn belonged to the field we just left
+                    rotate_accumulator = 0;
                 },
                 // for entering/exiting full access mode
                 ButtonEvent::DoublePress => {
@@ -627,8 +1990,92 @@ fn display_task(rx_sensor: mpsc::Receiver<SensorData>, rx_button: mpsc::Receiver
                 }
             }
         }
-        // set LEDs according to current state of accessibility
-        if full_access_mode {
+        // receive raw quarter-steps from the rotary encoder and collapse them into detents: in
+        // setup mode each detent steps the active field by 1 (same wrapping as a ShortPress),
+        // otherwise it scrolls through the sensors
+        while let Ok(InputEvent::Rotate(step)) = rx_rotate.try_recv() {
+            rotate_accumulator += step as i32;
+        }
+        while rotate_accumulator >= QUADRATURE_STEPS_PER_DETENT as i32 {
+            rotate_accumulator -= QUADRATURE_STEPS_PER_DETENT as i32;
+            if setup_mode != SetupMode::Idle {
+                match setup_mode {
+                    SetupMode::Hours => rtc_mutex.lock().unwrap().iterate_hour_by(1),
+                    SetupMode::Minutes => rtc_mutex.lock().unwrap().iterate_minute_by(1),
+                    SetupMode::Seconds => rtc_mutex.lock().unwrap().iterate_second_by(1),
+                    SetupMode::Date => rtc_mutex.lock().unwrap().iterate_date_by(1),
+                    SetupMode::Month => rtc_mutex.lock().unwrap().iterate_month_by(1),
+                    SetupMode::Year => rtc_mutex.lock().unwrap().iterate_year_by(1),
+                    SetupMode::AlarmHour => { alarm.0 = (alarm.0 + 1) % 24; rtc_mutex.lock().unwrap().set_alarm(alarm.0, alarm.1); },
+                    SetupMode::AlarmMinute => { alarm.1 = (alarm.1 + 1) % 60; rtc_mutex.lock().unwrap().set_alarm(alarm.0, alarm.1); },
+                    SetupMode::Target => { target_c = (target_c + TARGET_STEP_C).min(TARGET_MAX_C); *target_mutex.lock().unwrap() = target_c; },
+                    SetupMode::CalibrateDry | SetupMode::CalibrateWet | SetupMode::Idle => {}
+                }
+            } else {
+                current_sensor_i = (current_sensor_i + 1) % sensor_order.len();
+            }
+        }
+        while rotate_accumulator <= -(QUADRATURE_STEPS_PER_DETENT as i32) {
+            rotate_accumulator += QUADRATURE_STEPS_PER_DETENT as i32;
+            if setup_mode != SetupMode::Idle {
+                match setup_mode {
+                    SetupMode::Hours => rtc_mutex.lock().unwrap().iterate_hour_by(-1),
+                    SetupMode::Minutes => rtc_mutex.lock().unwrap().iterate_minute_by(-1),
+                    SetupMode::Seconds => rtc_mutex.lock().unwrap().iterate_second_by(-1),
+                    SetupMode::Date => rtc_mutex.lock().unwrap().iterate_date_by(-1),
+                    SetupMode::Month => rtc_mutex.lock().unwrap().iterate_month_by(-1),
+                    SetupMode::Year => rtc_mutex.lock().unwrap().iterate_year_by(-1),
+                    SetupMode::AlarmHour => { alarm.0 = (alarm.0 + 23) % 24; rtc_mutex.lock().unwrap().set_alarm(alarm.0, alarm.1); },
+                    SetupMode::AlarmMinute => { alarm.1 = (alarm.1 + 59) % 60; rtc_mutex.lock().unwrap().set_alarm(alarm.0, alarm.1); },
+                    SetupMode::Target => { target_c = (target_c - TARGET_STEP_C).max(TARGET_MIN_C); *target_mutex.lock().unwrap() = target_c; },
+                    SetupMode::CalibrateDry | SetupMode::CalibrateWet | SetupMode::Idle => {}
+                }
+            } else {
+                let len = sensor_order.len();
+                current_sensor_i = (current_sensor_i + len - 1) % len;
+            }
+        }
+        // receive mode overrides from the serial CLI (bypasses the can_be_unlocked gate, since
+        // a host terminal has already authenticated by whatever means got it a line to send)
+        while let Ok(mode_cmd) = rx_mode.try_recv() {
+            match mode_cmd {
+                ModeCommand::SetFull => { log::info!("[!] Entering Full Access Mode (serial)..."); full_access_mode = true; },
+                ModeCommand::SetRestricted => { log::info!("[!] Entering Restricted Mode (serial)..."); full_access_mode = false; },
+            }
+        }
+        // receive from the RTC task: the current time matched the programmed alarm
+        while let Ok(AlarmEvent::Ring) = rx_alarm.try_recv() {
+            log::info!("[!] Alarm ringing");
+            alarm_ringing = true;
+        }
+        // receive from the thermostat task: the heater output just flipped
+        while let Ok(thermostat_event) = rx_thermostat.try_recv() {
+            heater_on = matches!(thermostat_event, ThermostatEvent::HeaterOn);
+        }
+        // buzzer follows the alarm state directly
+        if alarm_ringing { buzzer_pin.set_high().unwrap(); } else { buzzer_pin.set_low().unwrap(); }
+        // set LEDs according to current state of accessibility; a low battery overrides
+        // everything and blinks the red LED instead, and a ringing alarm overrides the
+        // access-mode indication by flashing all three LEDs together
+        blink_state = !blink_state;
+        let low_battery = latest_battery.is_some_and(|v| v < LOW_BATTERY_THRESHOLD_V);
+        if low_battery {
+            if blink_state { red_pin.set_high().unwrap(); } else { red_pin.set_low().unwrap(); }
+            yellow_pin.set_low().unwrap();
+            green_pin.set_low().unwrap();
+        }
+        else if alarm_ringing {
+            if blink_state {
+                red_pin.set_high().unwrap();
+                yellow_pin.set_high().unwrap();
+                green_pin.set_high().unwrap();
+            } else {
+                red_pin.set_low().unwrap();
+                yellow_pin.set_low().unwrap();
+                green_pin.set_low().unwrap();
+            }
+        }
+        else if full_access_mode {
             red_pin.set_low().unwrap();
             yellow_pin.set_low().unwrap();
             green_pin.set_high().unwrap();
@@ -646,39 +2093,30 @@ fn display_task(rx_sensor: mpsc::Receiver<SensorData>, rx_button: mpsc::Receiver
         // receive from sensors
         while let Ok(sensor) = rx_sensor.try_recv() {
             match sensor {
-                SensorData::Temperature(_) => latest_data.insert("temperature", sensor),
-                SensorData::Moisture(_) => latest_data.insert("moisture", sensor),
-                SensorData::Light(_) => latest_data.insert("light", sensor),
-                SensorData::Pressure(_) => latest_data.insert("pressure", sensor)
-            };
+                SensorData::Temperature(_) => { latest_data.insert("temperature", sensor); },
+                SensorData::Moisture(_) => { latest_data.insert("moisture", sensor); },
+                SensorData::Light(_) => { latest_data.insert("light", sensor); },
+                SensorData::Pressure(_) => { latest_data.insert("pressure", sensor); },
+                SensorData::ProbeTemp(idx, temperature) => { latest_probes.insert(idx, temperature); },
+                SensorData::Battery(voltage) => { latest_battery = Some(voltage); },
+            }
         }
-        // display sensor data
+        // redraw the screen: clock (with the setup-mode field highlighted), current sensor
+        // reading, and the access-mode status row
+        screen.clear();
+        screen.draw_clock(last_tick.0, last_tick.1, last_tick.2, &setup_mode);
         if full_access_mode & (setup_mode == SetupMode::Idle) {
             let sensor_name = sensor_order[current_sensor_i];
-            match latest_data.get(sensor_name) {
-                Some(SensorData::Temperature(temperature)) => log::info!("[FULL ACCESS MODE] Temperature: {temperature}°C"),
-                Some(SensorData::Moisture(hour)) => log::info!("[FULL ACCESS MODE] Moisture: {hour}%"),
-                Some(SensorData::Light(brightness)) => log::info!("[FULL ACCESS MODE] Light: {}", if *brightness { "Bright" } else { "Dark" }),
-                Some(SensorData::Pressure(pressure)) => log::info!("[FULL ACCESS MODE] Pressure: {pressure}hPa"),
-                _ => log::info!("[FULL ACCESS MODE] {sensor_name}: N/A")
-            }
+            let thermostat_info = (sensor_name == "temperature").then_some((target_c, heater_on));
+            screen.draw_sensor(sensor_name, latest_data.get(sensor_name), thermostat_info);
         }
         else if !full_access_mode & (setup_mode == SetupMode::Idle) {
-            if let Some(SensorData::Temperature(temperature)) = latest_data.get("temperature") {
-                log::info!("[RESTRICTED MODE] Temperature: {temperature}°C");
-            }
-            else {
-                log::info!("[RESTRICTED MODE] Temperature: N/A");
-            }
-        }
-        else if setup_mode != SetupMode::Idle {
-            match setup_mode {
-                SetupMode::Hours => log::info!("[RTC SETUP MODE] Selected: Hours"),
-                SetupMode::Minutes => log::info!("[RTC SETUP MODE] Selected: Minutes"),
-                SetupMode::Seconds => log::info!("[RTC SETUP MODE] Selected: Seconds"),
-                SetupMode::Idle => {}
-            }
+            screen.draw_sensor("temperature", latest_data.get("temperature"), Some((target_c, heater_on)));
         }
+        screen.draw_status(full_access_mode, can_be_unlocked, &setup_mode, alarm, alarm_ringing, target_c);
+        screen.draw_probes(&latest_probes);
+        screen.draw_date(last_date.0, last_date.1, last_date.2, last_date.3, &setup_mode);
+        screen.present();
 
         thread::sleep(Duration::from_millis(1000));
     }
@@ -700,26 +2138,93 @@ fn main() {
     let red_pin = PinDriver::output(peripherals.pins.gpio5).unwrap();
     let yellow_pin = PinDriver::output(peripherals.pins.gpio6).unwrap();
     let green_pin = PinDriver::output(peripherals.pins.gpio7).unwrap();
+    // setup alarm buzzer
+    let buzzer_pin = PinDriver::output(peripherals.pins.gpio22).unwrap();
+    // setup thermostat heater/relay output
+    let heater_pin = PinDriver::output(peripherals.pins.gpio23).unwrap();
     // setup button
     let mut btn_pin = PinDriver::input(peripherals.pins.gpio0.downgrade()).unwrap();
     btn_pin.set_pull(Pull::Up).unwrap();
+    // setup rotary encoder: A/B quadrature channels plus its built-in push switch, which is
+    // wired through the same interrupt-driven press detection as the standalone button
+    let mut encoder_a_pin = PinDriver::input(peripherals.pins.gpio19.downgrade()).unwrap();
+    encoder_a_pin.set_pull(Pull::Up).unwrap();
+    let mut encoder_b_pin = PinDriver::input(peripherals.pins.gpio20.downgrade()).unwrap();
+    encoder_b_pin.set_pull(Pull::Up).unwrap();
+    let mut encoder_sw_pin = PinDriver::input(peripherals.pins.gpio21.downgrade()).unwrap();
+    encoder_sw_pin.set_pull(Pull::Up).unwrap();
     // setup DHT11 sensor
     let dht11_pin = PinDriver::input_output_od(peripherals.pins.gpio4.downgrade()).unwrap();
     let dht11 = Dht11::new(dht11_pin);
     // setup light sensor
     let light_pin = PinDriver::input(peripherals.pins.gpio38).unwrap();
+    // setup DS18B20 OneWire bus (one pin, shared by every probe found via ROM search)
+    let onewire_pin = PinDriver::input_output_od(peripherals.pins.gpio10.downgrade()).unwrap();
+    // setup battery divider: enable pin powers the divider only while sampling
+    let battery_enable_pin = PinDriver::output(peripherals.pins.gpio11).unwrap();
+    let battery_adc = AdcDriver::new(peripherals.adc1).unwrap();
+    let battery_adc_pin = peripherals.pins.gpio12;
+    // setup capacitive soil-moisture sensor; calibration constants are persisted in NVS
+    let moisture_adc = AdcDriver::new(peripherals.adc2).unwrap();
+    let moisture_adc_pin = peripherals.pins.gpio13;
+    let nvs_partition = EspDefaultNvsPartition::take().unwrap();
+    let moisture_calibration_mutex = Arc::new(Mutex::new(MoistureCalibration::load(nvs_partition)));
     // setup RTC pins
     let sclk_pin = PinDriver::output(peripherals.pins.gpio1).unwrap();
     let io_dat_pin = Some(PinDriver::output(peripherals.pins.gpio2).unwrap());
     let ce_pin = PinDriver::output(peripherals.pins.gpio3).unwrap();
     let rtc = RTCInterface::new(sclk_pin, io_dat_pin, ce_pin, delay);
     let rtc_mutex = Arc::new(Mutex::new(rtc)); // needs to be shared between threads
-
-
-    // create channels
-    let (tx_rtc, rx_rtc) = channel();
+    // setup SSD1306 OLED display (I2C); another panel fits here only as a `BinaryColor` `DrawTarget` implementing `FlushTarget`
+    let i2c = I2cDriver::new(peripherals.i2c0, peripherals.pins.gpio8, peripherals.pins.gpio9, &I2cConfig::new().baudrate(400.kHz().into())).unwrap();
+    let interface = I2CDisplayInterface::new(i2c);
+    let mut oled = Ssd1306::new(interface, DisplaySize128x64, DisplayRotation::Rotate0).into_buffered_graphics_mode();
+    oled.init().unwrap();
+    let screen = Screen::new(oled);
+    // setup CC1101 sub-GHz radio (SPI) for telemetry broadcast
+    let radio_spi = SpiDriver::new(peripherals.spi2, peripherals.pins.gpio14, peripherals.pins.gpio15, Some(peripherals.pins.gpio16), &SpiDriverConfig::new()).unwrap();
+    let radio_spi_device = SpiDeviceDriver::new(radio_spi, Some(peripherals.pins.gpio17), &SpiConfig::new()).unwrap();
+    let radio_gdo0 = PinDriver::input(peripherals.pins.gpio18.downgrade()).unwrap();
+    let radio = Cc1101::new(radio_spi_device, radio_gdo0);
+    // setup UART1 for the serial command-line interface
+    let serial_uart = UartDriver::new(
+        peripherals.uart1,
+        peripherals.pins.gpio43,
+        peripherals.pins.gpio44,
+        Option::<AnyIOPin>::None,
+        Option::<AnyIOPin>::None,
+        &UartConfig::new().baudrate(115_200.Hz().into()),
+    ).unwrap();
+    // setup UART2 for the typed postcard/COBS host protocol, alongside the text CLI on UART1
+    let protocol_uart = UartDriver::new(
+        peripherals.uart2,
+        peripherals.pins.gpio45,
+        peripherals.pins.gpio46,
+        Option::<AnyIOPin>::None,
+        Option::<AnyIOPin>::None,
+        &UartConfig::new().baudrate(115_200.Hz().into()),
+    ).unwrap();
+
+
+    // create channels; RTC ticks and sensor readings are broadcast to the display, the radio,
+    // and the serial CLI so a stall on any one consumer never blocks the others
+    let (tx_rtc_display, rx_rtc) = channel();
+    let (tx_rtc_radio, rx_rtc_radio) = channel();
+    let rtc_bus = Broadcaster::new(vec![tx_rtc_display, tx_rtc_radio]);
     let (tx_button, rx_button) = channel();
-    let (tx_sensor, rx_sensor) = channel();
+    let (tx_sensor_display, rx_sensor) = channel();
+    let (tx_sensor_radio, rx_sensor_radio) = channel();
+    let (tx_sensor_serial, rx_sensor_serial) = channel();
+    let (tx_sensor_protocol, rx_sensor_protocol) = channel();
+    let (tx_sensor_thermostat, rx_sensor_thermostat) = channel();
+    let sensor_bus = Broadcaster::new(vec![tx_sensor_display, tx_sensor_radio, tx_sensor_serial, tx_sensor_protocol, tx_sensor_thermostat]);
+    let (tx_calibrate, rx_calibrate) = channel();
+    let (tx_rotate, rx_rotate) = channel();
+    let (tx_mode, rx_mode) = channel();
+    let (tx_alarm, rx_alarm) = channel();
+    let (tx_thermostat, rx_thermostat) = channel();
+    // thermostat setpoint, shared between display_task (edits) and thermostat_task (reads)
+    let target_mutex = Arc::new(Mutex::new(22.0f32));
 
 
     // spawn tasks
@@ -732,7 +2237,7 @@ fn main() {
 
     let rtc_clone = Arc::clone(&rtc_mutex);
     thread::spawn(move || {
-        rtc_task(tx_rtc, rtc_clone);
+        rtc_task(rtc_bus, tx_alarm, rtc_clone);
     });
     log::info!("RTC thread spawned");
 
@@ -744,10 +2249,25 @@ fn main() {
         ..Default::default()
     }.set().unwrap();
 
+    let tx_button_encoder = tx_button.clone();
     thread::spawn(move || { button_task(tx_button, btn_pin); });
+    // the encoder's push switch goes through the same press-detection state machine, onto the
+    // same button channel, so either input device drives display_task identically
+    thread::spawn(move || { button_task(tx_button_encoder, encoder_sw_pin); });
     log::info!("Button thread spawned");
 
 
+    /* Encoder thread: CPU1 */
+    ThreadSpawnConfiguration {
+        name: Some("Encoder\0".as_bytes()),
+        pin_to_core: Some(esp_idf_hal::cpu::Core::Core1),
+        ..Default::default()
+    }.set().unwrap();
+
+    thread::spawn(move || { encoder_task(tx_rotate, encoder_a_pin, encoder_b_pin); });
+    log::info!("Encoder thread spawned");
+
+
     /* Sensors thread: CPU1 */
     ThreadSpawnConfiguration {
         name: Some("Sensors\0".as_bytes()),
@@ -755,10 +2275,94 @@ fn main() {
         ..Default::default()
     }.set().unwrap();
 
-    thread::spawn(move || { sensor_task(tx_sensor, dht11, light_pin); });
+    let tx_sensor_probes = sensor_bus.clone();
+    let tx_sensor_battery = sensor_bus.clone();
+    let tx_sensor_moisture = sensor_bus.clone();
+    thread::spawn(move || { sensor_task(sensor_bus, dht11, light_pin); });
     log::info!("Sensors thread spawned");
 
 
+    /* Moisture thread: CPU1 */
+    ThreadSpawnConfiguration {
+        name: Some("Moisture\0".as_bytes()),
+        pin_to_core: Some(esp_idf_hal::cpu::Core::Core1),
+        ..Default::default()
+    }.set().unwrap();
+
+    thread::spawn(move || { moisture_task(tx_sensor_moisture, rx_calibrate, moisture_calibration_mutex, moisture_adc, moisture_adc_pin); });
+    log::info!("Moisture thread spawned");
+
+
+    /* DS18B20 thread: CPU1 */
+    ThreadSpawnConfiguration {
+        name: Some("Probes\0".as_bytes()),
+        pin_to_core: Some(esp_idf_hal::cpu::Core::Core1),
+        ..Default::default()
+    }.set().unwrap();
+
+    thread::spawn(move || { ds18b20_task(tx_sensor_probes, onewire_pin); });
+    log::info!("DS18B20 thread spawned");
+
+
+    /* Battery thread: CPU1 */
+    ThreadSpawnConfiguration {
+        name: Some("Battery\0".as_bytes()),
+        pin_to_core: Some(esp_idf_hal::cpu::Core::Core1),
+        ..Default::default()
+    }.set().unwrap();
+
+    thread::spawn(move || { battery_task(tx_sensor_battery, battery_enable_pin, battery_adc, battery_adc_pin); });
+    log::info!("Battery thread spawned");
+
+
+    /* Thermostat thread: CPU1 */
+    ThreadSpawnConfiguration {
+        name: Some("Thermostat\0".as_bytes()),
+        pin_to_core: Some(esp_idf_hal::cpu::Core::Core1),
+        ..Default::default()
+    }.set().unwrap();
+
+    let target_clone = Arc::clone(&target_mutex);
+    thread::spawn(move || { thermostat_task(rx_sensor_thermostat, tx_thermostat, target_clone, heater_pin); });
+    log::info!("Thermostat thread spawned");
+
+
+    /* Radio thread: CPU1 */
+    ThreadSpawnConfiguration {
+        name: Some("Radio\0".as_bytes()),
+        pin_to_core: Some(esp_idf_hal::cpu::Core::Core1),
+        ..Default::default()
+    }.set().unwrap();
+
+    thread::spawn(move || { radio_task(rx_sensor_radio, rx_rtc_radio, radio); });
+    log::info!("Radio thread spawned");
+
+
+    /* Serial thread: CPU1 */
+    ThreadSpawnConfiguration {
+        name: Some("Serial\0".as_bytes()),
+        pin_to_core: Some(esp_idf_hal::cpu::Core::Core1),
+        ..Default::default()
+    }.set().unwrap();
+
+    let rtc_clone = Arc::clone(&rtc_mutex);
+    let tx_mode_protocol = tx_mode.clone();
+    thread::spawn(move || { serial_task(serial_uart, rtc_clone, rx_sensor_serial, tx_mode); });
+    log::info!("Serial thread spawned");
+
+
+    /* Protocol thread: CPU1 */
+    ThreadSpawnConfiguration {
+        name: Some("Protocol\0".as_bytes()),
+        pin_to_core: Some(esp_idf_hal::cpu::Core::Core1),
+        ..Default::default()
+    }.set().unwrap();
+
+    let rtc_clone = Arc::clone(&rtc_mutex);
+    thread::spawn(move || { protocol_task(protocol_uart, rtc_clone, rx_sensor_protocol, tx_mode_protocol); });
+    log::info!("Protocol thread spawned");
+
+
     /* Display thread: CPU1 */
     ThreadSpawnConfiguration {
         name: Some("Display\0".as_bytes()),
@@ -767,9 +2371,149 @@ fn main() {
     }.set().unwrap();
 
     let rtc_clone = Arc::clone(&rtc_mutex);
-    thread::spawn(move || { display_task(rx_sensor, rx_button, rx_rtc, red_pin, yellow_pin, green_pin, rtc_clone); });
+    thread::spawn(move || { display_task(rx_sensor, rx_button, rx_rtc, rx_rotate, rx_mode, rx_alarm, rx_thermostat, red_pin, yellow_pin, green_pin, buzzer_pin, rtc_clone, tx_calibrate, target_mutex, screen); });
     log::info!("Display thread spawned");
 
 
     log::info!("Main thread finished")
 }
+
+#[cfg(test)]
+mod one_wire_search_tests {
+    use super::{search_rom_pass, OneWireIo};
+
+    /// Simulates an N-device 1-Wire bus for exercising `search_rom_pass` without real hardware:
+    /// on each bit pair it reports agreement/discrepancy across the devices still "in the
+    /// running" for the current pass, then `write_bit` narrows that set to the chosen direction.
+    struct MockBus {
+        roms: Vec<[u8; 8]>,
+        active: Vec<usize>,
+        bit_i: usize,
+        pending_complement: bool,
+        last_bit: bool,
+        last_complement: bool,
+    }
+
+    impl MockBus {
+        fn new(roms: Vec<[u8; 8]>) -> Self {
+            let active = (0..roms.len()).collect();
+            MockBus {
+                roms,
+                active,
+                bit_i: 0,
+                pending_complement: false,
+                last_bit: false,
+                last_complement: false,
+            }
+        }
+
+        fn reset_pass(&mut self) {
+            self.active = (0..self.roms.len()).collect();
+            self.bit_i = 0;
+        }
+
+        fn device_bit(rom: &[u8; 8], bit_i: usize) -> bool {
+            (rom[bit_i / 8] >> (bit_i % 8)) & 0x01 == 1
+        }
+    }
+
+    impl OneWireIo for MockBus {
+        fn read_bit(&mut self) -> bool {
+            if !self.pending_complement {
+                let first = Self::device_bit(&self.roms[self.active[0]], self.bit_i);
+                let unanimous = self.active.iter().all(|&i| Self::device_bit(&self.roms[i], self.bit_i) == first);
+                self.pending_complement = true;
+                self.last_bit = if unanimous { first } else { false };
+                self.last_complement = if unanimous { !first } else { false };
+                self.last_bit
+            } else {
+                self.pending_complement = false;
+                self.last_complement
+            }
+        }
+
+        fn write_bit(&mut self, bit: bool) {
+            self.active.retain(|&i| Self::device_bit(&self.roms[i], self.bit_i) == bit);
+            self.bit_i += 1;
+        }
+    }
+
+    fn search_all(mut roms: Vec<[u8; 8]>) -> Vec<[u8; 8]> {
+        roms.sort();
+        let mut bus = MockBus::new(roms);
+        let mut found = Vec::new();
+        let mut rom = [0u8; 8];
+        let mut last_discrepancy: i32 = -1;
+        loop {
+            bus.reset_pass();
+            match search_rom_pass(last_discrepancy, &mut rom, &mut bus) {
+                None => break,
+                Some(discrepancy) => {
+                    found.push(rom);
+                    last_discrepancy = discrepancy;
+                    if last_discrepancy == -1 { break; }
+                }
+            }
+        }
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn finds_single_device() {
+        let rom = [0x28, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x01];
+        assert_eq!(search_all(vec![rom]), vec![rom]);
+    }
+
+    #[test]
+    fn finds_two_devices() {
+        let a = [0x28, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01];
+        let b = [0x28, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02];
+        assert_eq!(search_all(vec![a, b]), {
+            let mut v = vec![a, b];
+            v.sort();
+            v
+        });
+    }
+
+    #[test]
+    fn finds_three_devices_with_multiple_discrepancies() {
+        let a = [0x28, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01];
+        let b = [0x28, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02];
+        let c = [0x28, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03];
+        let mut expected = vec![a, b, c];
+        expected.sort();
+        assert_eq!(search_all(vec![a, b, c]), expected);
+    }
+
+    #[test]
+    fn finds_five_devices_differing_only_in_last_byte() {
+        let roms: Vec<[u8; 8]> = (0u8..5)
+            .map(|i| [0x28, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, i])
+            .collect();
+        let mut expected = roms.clone();
+        expected.sort();
+        assert_eq!(search_all(roms), expected);
+    }
+
+    /// A bus that immediately reports "no device responded" (bit and its complement both 1),
+    /// simulating a reset that found no presence pulse, a dropped device mid-search, or noise.
+    struct NoResponseBus;
+
+    impl OneWireIo for NoResponseBus {
+        fn read_bit(&mut self) -> bool {
+            true
+        }
+
+        fn write_bit(&mut self, _bit: bool) {}
+    }
+
+    #[test]
+    fn bus_error_returns_none_without_corrupting_rom() {
+        let mut rom = [0xAAu8; 8];
+        let result = search_rom_pass(-1, &mut rom, &mut NoResponseBus);
+        assert_eq!(result, None);
+        // A caller must not push `rom` into its results when this returns None.
+        assert_eq!(rom, [0xAAu8; 8]);
+    }
+}